@@ -1,10 +1,26 @@
-use crate::server::{HttpRequest,HttpResponse,StatusCode};
-use std::{collections::HashMap, io::{Read, Write}, net::{TcpListener, TcpStream}};
+use crate::connection::{Connection, Listener, ReadError, Socket};
+use crate::config::ServerConfig;
+use crate::handle::{BoundAddr, FinishGuard, ServerHandle, ServerState};
+use crate::router::{RouteTree, Router, ANY_METHOD};
+use crate::error::HttpError;
+use crate::server::{HttpRequest,HttpResponse,Respondable,StatusCode};
+use std::{any::Any, io, net::{TcpListener, ToSocketAddrs}, panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}, Arc, Mutex}, thread, time::{Duration, Instant}};
+
+#[cfg(unix)]
+use std::{os::unix::net::UnixListener, path::Path};
+
+/// How often idle connections check whether the server is shutting down.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a connection is checked for an already sent request once the server is shutting down.
+const SHUTDOWN_READ_GRACE: Duration = Duration::from_millis(10);
 /// Type alias for a function that handles HTTP requests and returns responses.
 /// 
 /// Handlers take an `HttpRequest` and return an `HttpResponse`.
 /// These functions are stored in the `App` router for specific paths.
-pub type Handler = Box<dyn Fn(HttpRequest) -> HttpResponse>;
+/// 
+/// Handlers are shared between the worker threads of the server,
+/// so they must be `Send + Sync`.
+pub type Handler = Box<dyn Fn(HttpRequest) -> HttpResponse + Send + Sync>;
 
 
 
@@ -13,14 +29,141 @@ pub type Handler = Box<dyn Fn(HttpRequest) -> HttpResponse>;
 /// 
 /// `Response` - use this when you do not want to go through the next middlewares.
 /// `Continue` - use this when you want to continue the middleware chain.
+/// 
+/// A middleware which changes the response, e.g. to add headers, is added with [`App::wrap`] instead.
 #[derive(Debug)]
 pub enum MiddlewareResult {
     Response(HttpResponse),
-    Continue(HttpRequest)
+    Continue(HttpRequest),
+}
+
+
+/// Type alias for a function which renders the errors of an application.
+/// 
+/// See [`App::set_error_handler`].
+pub type ErrorHandler = Box<dyn Fn(&HttpError) -> HttpResponse + Send + Sync>;
+
+
+/// A panic of a handler or middleware, reported to the panic handler of an `App`.
+#[derive(Debug, Clone)]
+pub struct HandlerPanic {
+    /// The method of the request.
+    pub method: String,
+    /// The decoded path of the request.
+    pub path: String,
+    /// The message the code panicked with.
+    pub message: String,
+}
+
+impl HandlerPanic {
+    fn new(method: String, path: String, payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => "Box<dyn Any>".to_string(),
+            },
+        };
+        Self { method, path, message }
+    }
+}
+
+/// Type alias for a function which is told about panics of handlers and middlewares.
+/// 
+/// See [`App::set_panic_handler`].
+pub type PanicHandler = Box<dyn Fn(&HandlerPanic) + Send + Sync>;
+
+
+/// Type alias for a middleware function.
+/// 
+/// Like handlers, middlewares are shared between the worker threads of the server,
+/// so they must be `Send + Sync`.
+pub type Middleware = Box<dyn Fn(HttpRequest) -> MiddlewareResult + Send + Sync + 'static>;
+
+/// Type alias for a middleware which runs around the rest of the chain, see [`App::wrap`].
+/// 
+/// It gets the request and the rest of the chain, and returns the response.
+/// It can change the request before calling [`Next::run`], change the response afterwards,
+/// or answer without calling it at all.
+pub type AroundMiddleware = Box<dyn Fn(HttpRequest, Next<'_>) -> HttpResponse + Send + Sync + 'static>;
+
+/// The rest of a middleware chain, ending with the handler of the route.
+pub struct Next<'a> {
+    middlewares: &'a [Middleware],
+    /// The around middlewares, with the number of middlewares added before them.
+    around: &'a [(usize, AroundMiddleware)],
+    /// The number of middlewares which already ran.
+    position: usize,
+    handler: &'a dyn Fn(HttpRequest) -> HttpResponse,
+}
+
+impl Next<'_> {
+    /// Runs the next middlewares and the handler, and returns their response.
+    pub fn run(self, req: HttpRequest) -> HttpResponse {
+        // The middlewares may have been removed from `App::middlewares` since, the around ones still run
+        if let Some(((added_after, md), rest)) = self.around.split_first()
+            && (*added_after <= self.position || self.middlewares.is_empty())
+        {
+            return md(req, Next { around: rest, ..self });
+        }
+
+        match self.middlewares.split_first() {
+            Some((md, rest)) => match md(req) {
+                MiddlewareResult::Response(response) => response,
+                MiddlewareResult::Continue(req) => Next { middlewares: rest, position: self.position + 1, ..self }.run(req),
+            },
+            None => (self.handler)(req),
+        }
+    }
 }
 
+impl std::fmt::Debug for Next<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Next")
+            .field("middlewares", &(self.middlewares.len() + self.around.len()))
+            .finish_non_exhaustive()
+    }
+}
 
-pub type Middleware = Box<dyn Fn(HttpRequest) -> MiddlewareResult + 'static>;
+/// Runs a middleware chain, then the handler.
+pub(crate) fn run_middlewares<F>(middlewares: &[Middleware], around: &[(usize, AroundMiddleware)], req: HttpRequest, handler: F) -> HttpResponse
+where
+    F: Fn(HttpRequest) -> HttpResponse,
+{
+    Next { middlewares, around, position: 0, handler: &handler }.run(req)
+}
+
+/// Wraps a handler with middlewares which only run for its route.
+/// 
+/// Unlike the middlewares of the `App`, they run after the route has matched,
+/// so they can read `req.path_params`. They run after the middlewares of the `App`,
+/// in the given order.
+/// 
+/// # Arguments
+/// 
+/// * `middlewares` - the middlewares of the route
+/// * `handler` - the handler of the route
+/// 
+/// # Examples
+/// 
+/// ```
+/// use http::{with_middleware, App, HttpRequest, HttpResponse, Middleware, MiddlewareResult, Respondable, StatusCode};
+/// 
+/// let mut app = App::new();
+/// let middlewares: Vec<Middleware> = vec![Box::new(|req: HttpRequest| {
+///     if req.body.len() > 1024 {
+///         return MiddlewareResult::Response(HttpResponse::new(StatusCode::BadRequest, "Too long"));
+///     }
+///     MiddlewareResult::Continue(req)
+/// })];
+/// app.post("/login".to_string(), with_middleware(middlewares, |_| "Welcome".into_response()));
+/// ```
+pub fn with_middleware<F>(middlewares: Vec<Middleware>, handler: F) -> impl Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+where
+    F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    move |req| run_middlewares(&middlewares, &[], req, &handler)
+}
 
 /// The main application struct that handles HTTP routing and request processing.
 /// 
@@ -41,9 +184,15 @@ pub type Middleware = Box<dyn Fn(HttpRequest) -> MiddlewareResult + 'static>;
 /// app.listen(3000);
 /// ```
 pub struct App {
-    pub handlers: HashMap<String, HashMap<String,Handler>>,
-    pub param_handlers: Vec<(String,String,Handler)>,
+    routes: RouteTree,
+    /// The middlewares added with [`App::use_middleware`], in order.
     pub middlewares: Vec<Middleware>,
+    /// The middlewares added with [`App::wrap`], with the number of middlewares added before them.
+    around: Vec<(usize, AroundMiddleware)>,
+    pub config: ServerConfig,
+    error_handler: Option<ErrorHandler>,
+    expose_internal_errors: bool,
+    panic_handler: Option<PanicHandler>,
 }
 
 impl App {
@@ -60,18 +209,160 @@ impl App {
     /// ```
     pub fn new() -> Self {
         Self {
-            handlers: HashMap::new(),
-            param_handlers:Vec::new(),
+            routes: RouteTree::new(),
             middlewares: Vec::new(),
+            around: Vec::new(),
+            config: ServerConfig::default(),
+            error_handler: None,
+            expose_internal_errors: true,
+            panic_handler: None,
         }
     }
 
+    /// Sets the function which is told when a handler or middleware panics.
+    /// 
+    /// A panic does not take the server down: the request is answered with
+    /// `500 Internal Server Error`, rendered by the error handler, and the server keeps
+    /// serving other requests. Without a panic handler, panics are logged to stderr
+    /// with the method and path of the request.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `handler` - a function reporting the panic, e.g. to a monitoring service
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{App, HandlerPanic};
+    /// 
+    /// let mut app = App::new();
+    /// app.set_panic_handler(|p: &HandlerPanic| {
+    ///     eprintln!("{} {} panicked: {}", p.method, p.path, p.message);
+    /// });
+    /// ```
+    pub fn set_panic_handler<F>(&mut self, handler: F)
+    where F: Fn(&HandlerPanic) + Send + Sync + 'static {
+        self.panic_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the function rendering errors.
+    /// 
+    /// Every response created from an [`HttpError`] is rendered with it once the middlewares
+    /// have run: errors returned by handlers, rejected extractors, and `404 Not Found`
+    /// and `405 Method Not Allowed` responses. The headers of the original response,
+    /// e.g. `Allow` or `Set-Cookie`, are kept unless the handler sets them too.
+    /// Without it, errors are sent as plain text.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `handler` - a function creating the response of an error
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{App, HttpError, HttpResponse};
+    /// 
+    /// let mut app = App::new();
+    /// app.set_error_handler(|e: &HttpError| {
+    ///     let mut response = HttpResponse::new(e.status, e.to_json().to_string());
+    ///     response.headers.insert("Content-Type", "application/json");
+    ///     response
+    /// });
+    /// app.set_expose_internal_errors(false);
+    /// ```
+    pub fn set_error_handler<F>(&mut self, handler: F)
+    where F: Fn(&HttpError) -> HttpResponse + Send + Sync + 'static {
+        self.error_handler = Some(Box::new(handler));
+    }
+
+    /// Sets whether the messages and details of server errors (`5xx`) are sent to clients.
+    /// 
+    /// They are sent by default. Hidden errors are logged, and sent with the reason phrase
+    /// of their status as message, e.g. `Internal Server Error`, so internal details
+    /// do not leak in production.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `expose` - `false` to hide the messages of server errors
+    pub fn set_expose_internal_errors(&mut self, expose: bool) {
+        self.expose_internal_errors = expose;
+    }
+
+    /// Replaces the server configuration.
+    /// 
+    /// See [`ServerConfig`] for the available settings and their defaults.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `config` - the new configuration
+    pub fn set_config(&mut self, config: ServerConfig) {
+        self.config = config;
+        self.config.workers = self.config.workers.max(1);
+        self.config.max_keep_alive_requests = self.config.max_keep_alive_requests.max(1);
+    }
+
+    /// Sets the number of worker threads used to serve connections.
+    /// 
+    /// Defaults to 64. Idle keep-alive connections hold a worker until their next request,
+    /// see [`ServerConfig::workers`]. A value of `0` is treated as `1`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `workers` - the number of connections served in parallel
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::App;
+    /// 
+    /// let mut app = App::new();
+    /// app.set_workers(8);
+    /// ```
+    pub fn set_workers(&mut self, workers: usize) {
+        self.config.workers = workers.max(1);
+    }
+
+    /// Sets how long an idle persistent connection is kept open.
+    /// 
+    /// Defaults to 5 seconds.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `timeout` - the time to wait for the next request on a connection
+    pub fn set_keep_alive_timeout(&mut self, timeout: Duration) {
+        self.config.keep_alive_timeout = timeout;
+    }
+
+    /// Sets the maximum number of requests served on a single connection.
+    /// 
+    /// The connection is closed after the last response. Defaults to 100.
+    /// A value of `1` disables persistent connections.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `max` - the maximum number of requests per connection
+    pub fn set_max_keep_alive_requests(&mut self, max: usize) {
+        self.config.max_keep_alive_requests = max.max(1);
+    }
+
+    /// Sets how long [`ServerHandle::shutdown`] waits for in-flight requests to finish.
+    /// 
+    /// Defaults to 30 seconds.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `timeout` - the deadline for a graceful shutdown
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.config.shutdown_timeout = timeout;
+    }
+
     /// Registers a handler function for a `GET` request to a specific path.
     /// 
     /// When a `GET` request with the given URI is received, the provided handler will be
     /// called to generate the response.
     /// 
     /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
     /// 
     /// # Arguments
     /// 
@@ -95,33 +386,48 @@ impl App {
     /// ```
     pub fn get<F>(&mut self, path: String, handler: F)
     where 
-        F: Fn(HttpRequest) -> HttpResponse + 'static,
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
          {
-            let get =String::from("GET");
-            let method_map = self.handlers.entry(path).or_insert(HashMap::new());
-
-            // rewrite if exists
-            if method_map.contains_key(&get) {
-                method_map.remove(&get);
-            }
-            method_map.insert(get,Box::new(handler));
+            self.routes.insert("GET", &path, Box::new(handler));
          }
     
     
 
     /// Adds a handler with path parameters
     /// 
+    /// Parameters are whole segments wrapped in curly braces:
+    /// 
+    /// * `{id}` matches any non-empty segment,
+    /// * `{id:u64}` matches only if the segment parses as the type, which can be any integer
+    ///   or float type or `bool`,
+    /// * `{slug:[a-z0-9-]+}` matches only if the whole segment matches the regular expression,
+    /// * `{page?}` is optional, so `/posts/{page?}` also matches `/posts`,
+    ///   optional parameters must be the last segments,
+    /// * `{*path}` as the last segment captures the rest of the path, e.g. `css/site.css`.
+    /// 
+    /// Their values are available in `req.path_params`, and parsed with [`HttpRequest::path_param`].
+    /// 
+    /// When several routes match a path, the most specific one wins:
+    /// a static segment beats a parameter, which beats a catch-all parameter,
+    /// so `/users/me` is preferred over `/users/{id}` regardless of the registration order.
+    /// A parameter with a type constraint like `{id:u64}` is tried first, then one with a regex,
+    /// then an unconstrained one.
+    /// 
     /// # Arguments
     /// 
     /// * `path` - a path pattern with parameters wrapped in curly braces
     /// * `method` - HTTP method
     /// * `handler` - a specified handler
     /// 
+    /// # Panics
     /// 
+    /// Panics if the pattern is invalid, or if it conflicts with a registered route,
+    /// e.g. `/users/{name}` after `/users/{id}`, or `/users/{id:i64}` after `/users/{id:u64}`,
+    /// as it would be ambiguous which one should match.
     pub fn add_param_handler<F>(&mut self, path:String,method: String, handler: F)
     where
-         F: Fn(HttpRequest) -> HttpResponse + 'static {
-            self.param_handlers.push((path,method,Box::new(handler)));
+         F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static {
+            self.routes.insert(&method, &path, Box::new(handler));
          }
 
     /// Registers a handler function for a `POST` request to a specific path.
@@ -130,6 +436,7 @@ impl App {
     /// called to generate the response.
     /// 
     /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
     /// 
     /// # Arguments
     /// 
@@ -153,14 +460,9 @@ impl App {
     /// ```
     pub fn post<F>(&mut self, path: String, handler: F)
     where 
-        F: Fn(HttpRequest) -> HttpResponse + 'static,
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
          {
-            let post =String::from("POST");
-            let method_map = self.handlers.entry(path).or_insert(HashMap::new());
-            if method_map.contains_key(&post) {
-                method_map.remove(&post);
-            }
-            method_map.insert(post,Box::new(handler));
+            self.routes.insert("POST", &path, Box::new(handler));
          }
     
 
@@ -171,6 +473,7 @@ impl App {
     /// called to generate the response.
     /// 
     /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
     /// 
     /// # Arguments
     /// 
@@ -194,14 +497,9 @@ impl App {
     /// ```
     pub fn put<F>(&mut self, path: String, handler: F)
     where 
-        F: Fn(HttpRequest) -> HttpResponse + 'static,
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
          {
-            let put =String::from("PUT");
-            let method_map = self.handlers.entry(path).or_insert(HashMap::new());
-            if method_map.contains_key(&put) {
-                method_map.remove(&put);
-            }
-            method_map.insert(put,Box::new(handler));
+            self.routes.insert("PUT", &path, Box::new(handler));
          }
     
 
@@ -213,6 +511,7 @@ impl App {
     /// called to generate the response.
     /// 
     /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
     /// 
     /// # Arguments
     /// 
@@ -236,17 +535,154 @@ impl App {
     /// ```
     pub fn delete<F>(&mut self, path: String, handler: F)
     where 
-        F: Fn(HttpRequest) -> HttpResponse + 'static,
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
          {
-            let delete =String::from("DELETE");
-            let method_map = self.handlers.entry(path).or_insert(HashMap::new());
-            if method_map.contains_key(&delete) {
-                method_map.remove(&delete);
-            }
-            method_map.insert(delete,Box::new(handler));
+            self.routes.insert("DELETE", &path, Box::new(handler));
+         }
+
+    /// Registers a handler function for a `PATCH` request to a specific path.
+    /// 
+    /// When a `PATCH` request with the given URI is received, the provided handler will be
+    /// called to generate the response.
+    /// 
+    /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The URI path to register the handler for
+    /// * `handler` - A function that takes an `HttpRequest` and return an `HttpResponse`
+    pub fn patch<F>(&mut self, path: String, handler: F)
+    where 
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+         {
+            self.routes.insert("PATCH", &path, Box::new(handler));
+         }
+
+    /// Registers a handler function for a `HEAD` request to a specific path.
+    /// 
+    /// When a `HEAD` request with the given URI is received, the provided handler will be
+    /// called to generate the response.
+    /// 
+    /// Without it, `HEAD` requests are answered by the `GET` handler of the route.
+    /// The body of a response to a `HEAD` request is never sent, but its headers,
+    /// including `Content-Length`, are.
+    /// 
+    /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The URI path to register the handler for
+    /// * `handler` - A function that takes an `HttpRequest` and return an `HttpResponse`
+    pub fn head<F>(&mut self, path: String, handler: F)
+    where 
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+         {
+            self.routes.insert("HEAD", &path, Box::new(handler));
+         }
+
+    /// Registers a handler function for an `OPTIONS` request to a specific path.
+    /// 
+    /// When an `OPTIONS` request with the given URI is received, the provided handler will be
+    /// called to generate the response.
+    /// 
+    /// Without it, `OPTIONS` requests are answered with `204 No Content`
+    /// and an `Allow` header listing the methods of the route.
+    /// 
+    /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The URI path to register the handler for
+    /// * `handler` - A function that takes an `HttpRequest` and return an `HttpResponse`
+    pub fn options<F>(&mut self, path: String, handler: F)
+    where 
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+         {
+            self.routes.insert("OPTIONS", &path, Box::new(handler));
+         }
+
+    /// Registers a handler function for every method of a specific path.
+    /// 
+    /// Handlers registered for a single method take precedence over it.
+    /// 
+    /// If a handler on this route exists, it will be rewritten.
+    /// The path may contain parameters, see [`App::add_param_handler`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The URI path to register the handler for
+    /// * `handler` - A function that takes an `HttpRequest` and return an `HttpResponse`
+    pub fn any<F>(&mut self, path: String, handler: F)
+    where 
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+         {
+            self.routes.insert(ANY_METHOD, &path, Box::new(handler));
          }
     
 
+
+    /// Mounts the routes of a [`Router`] under a prefix.
+    /// 
+    /// The prefix may contain parameters, e.g. `/orgs/{org}`, which are available
+    /// to the handlers of the router like their own parameters.
+    /// The routes then have several parameters, which the [`Path`](crate::Path) extractor
+    /// does not support, read them with [`PathParams`](crate::PathParams) instead.
+    /// The route `/` of the router is served at the prefix itself.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `prefix` - the path the routes are mounted under, e.g. `/api/v1`
+    /// * `router` - the routes to mount
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the prefix is invalid, or if a route conflicts with a registered route,
+    /// see [`App::add_param_handler`].
+    pub fn nest(&mut self, prefix: &str, router: Router) {
+        router.mount(prefix, &mut self.routes);
+    }
+
+    /// Adds a group of routes under a prefix.
+    /// 
+    /// The routes are registered on a [`Router`], which is then nested under the prefix.
+    /// Middlewares added to it only run for the routes of the group.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `prefix` - the path the routes are mounted under, e.g. `/admin`
+    /// * `build` - a function registering the routes and middlewares of the group
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{App, HttpResponse, MiddlewareResult, Respondable, StatusCode};
+    /// 
+    /// let mut app = App::new();
+    /// app.group("/admin", |admin| {
+    ///     admin.use_middleware(|req| {
+    ///         if req.headers.get("Authorization").is_none() {
+    ///             return MiddlewareResult::Response(HttpResponse::new(StatusCode::Unauthorized, "Unauthorized"));
+    ///         }
+    ///         MiddlewareResult::Continue(req)
+    ///     });
+    ///     admin.get("/stats".to_string(), |_| "42 users".into_response());
+    /// });
+    /// ```
+    /// 
+    /// # Panics
+    /// 
+    /// Panics like [`App::nest`].
+    pub fn group<F>(&mut self, prefix: &str, build: F)
+    where
+        F: FnOnce(&mut Router),
+    {
+        let mut router = Router::new();
+        build(&mut router);
+        self.nest(prefix, router);
+    }
     
     /// Serving static files in a specified directory.
     /// 
@@ -291,17 +727,19 @@ impl App {
 
 
             if file_path.is_file() {
-                let file_content = match std::fs::read_to_string(&file_path) {
-                    Ok(s) => s,
+                let file_content = match std::fs::read(&file_path) {
+                    Ok(bytes) => bytes,
                     Err(e) => {
                         eprintln!("Error reading file {:?}: {}", file_path, e);
                         continue;
                     }
                 };
+                let content_type = Self::get_content_type(&file_path);
                 let route_path = format!("{}/{}", serving_path,file_name.to_string_lossy());
                 self.get(route_path, move |_| {
-                    let cloned_content = file_content.clone();
-                    HttpResponse::new(StatusCode::Ok, String::from(cloned_content)) 
+                    let mut response = HttpResponse::new(StatusCode::Ok, file_content.clone());
+                    response.headers.insert("Content-Type", content_type);
+                    response
                 });
             } else {
                 self.serve_static_dir(file_path.to_string_lossy().to_string(), format!("{}/{}",serving_path,file_name.to_string_lossy()));
@@ -337,6 +775,9 @@ impl App {
     /// "Second!"
     /// ```
     /// 
+    /// They run for every request, before the route is matched. Middlewares which only run
+    /// for some routes are added with [`Router::use_middleware`] or [`with_middleware`].
+    /// 
     /// 
     /// # Arguments
     /// 
@@ -344,15 +785,48 @@ impl App {
     /// 
     /// 
     pub fn use_middleware<F>(&mut self, md: F)
-    where F: Fn(HttpRequest) -> MiddlewareResult + 'static {
+    where F: Fn(HttpRequest) -> MiddlewareResult + Send + Sync + 'static {
         self.middlewares.push(Box::new(md));
     }
 
+    /// Adds a middleware which runs around the rest of the chain.
+    /// 
+    /// The middleware gets the request and a [`Next`] continuation. Calling `next.run(req)`
+    /// runs the following middlewares and the handler and returns their response,
+    /// so the middleware can run code both before and after the handler.
+    /// Middlewares added with `wrap` and [`App::use_middleware`] run in the order they were added.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `md` - a middleware to be added.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{App, HttpRequest, Next};
+    /// use std::time::Instant;
+    /// 
+    /// let mut app = App::new();
+    /// app.wrap(|req: HttpRequest, next: Next| {
+    ///     let start = Instant::now();
+    ///     let mut response = next.run(req);
+    ///     response.headers.insert("X-Response-Time", format!("{}us", start.elapsed().as_micros()));
+    ///     response
+    /// });
+    /// ```
+    pub fn wrap<F>(&mut self, md: F)
+    where F: Fn(HttpRequest, Next<'_>) -> HttpResponse + Send + Sync + 'static {
+        self.around.push((self.middlewares.len(), Box::new(md)));
+    }
+
 
     /// Processes an incoming HTTP request and returns the appropriate response.
     /// 
-    /// Looks up the request URI in the registered handlers and calls the corresponding handler.
+    /// Looks up the decoded request path in the registered handlers and calls the corresponding handler.
+    /// The query string is not part of the match, it is available in `req.query_params`.
     /// If no handler is found, returns a 404 Not Found response.
+    /// If a handler or middleware panics, returns a 500 Internal Server Error response
+    /// and reports the panic, see [`App::set_panic_handler`].
     /// 
     /// # Arguments
     /// 
@@ -361,7 +835,7 @@ impl App {
     /// # Examples
     /// 
     /// ```
-    /// use mhttp::{App, HttpRequest, HttpResponse, StatusCode};
+    /// use http::{App, HttpRequest, Respondable};
     /// 
     /// let mut app = App::new();
     /// 
@@ -369,73 +843,106 @@ impl App {
     ///     "Test response".to_string().into_response()
     /// });
     /// 
-    /// let req = HttpRequest {
-    ///     method: "GET".to_string(),
-    ///     uri: "/test".to_string(),
-    ///     version: "HTTP/1.1".to_string(),
-    ///     headers: std::collections::HashMap::new(),
-    ///     body: String::new(),
-    /// };
+    /// let req = HttpRequest::parse("GET /test?page=2 HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
     /// 
     /// let response = app.handle_request(req);
     /// assert_eq!(response.body, "Test response");
     /// ```
     pub fn handle_request(&self, req: HttpRequest) -> HttpResponse {
-        let mut current_request = req;
+        let (method, path) = (req.method.clone(), req.path.clone());
 
-        for md in &self.middlewares {
-            match md(current_request) {
-                MiddlewareResult::Response(response) => return response,
-                MiddlewareResult::Continue(req) => current_request = req,
-            }
-        }
+        // The request is dropped with the panic, and the app itself is never changed while handling it
+        let response = panic::catch_unwind(AssertUnwindSafe(|| run_middlewares(&self.middlewares, &self.around, req, |req| self.route(req))))
+            .unwrap_or_else(|payload| {
+                self.report_panic(HandlerPanic::new(method, path, payload));
+                HttpError::internal("Internal Server Error").into_response()
+            });
 
-            if let Some(method_map) = self.handlers.get(&current_request.uri.to_string()) {
-            if let Some(handler) = method_map.get(&current_request.method) {
-                return  handler(current_request);
-            }
-            }
+        // A status line with such a code cannot be written
+        let response = if response.status.is_valid() {
+            response
+        } else {
+            HttpError::internal(format!("Invalid status code {}", response.status.as_u16())).into_response()
+        };
 
-            for (pattern, method,handler) in &self.param_handlers {
-                if method == &current_request.method {
-                    if let Some(path_params) = Self::match_route_pattern(pattern,&current_request.uri) {
-                        current_request.path_params = path_params;
-                        return handler(current_request);
-                    }
+        panic::catch_unwind(AssertUnwindSafe(|| self.render_error(response))).unwrap_or_else(|_| {
+            eprintln!("Error rendering an error response: the error handler panicked");
+            HttpError::internal("Internal Server Error").into_response()
+        })
+    }
+
+    /// Tells the panic handler about a panic, logs it if there is none.
+    fn report_panic(&self, report: HandlerPanic) {
+        match &self.panic_handler {
+            Some(handler) => {
+                if panic::catch_unwind(AssertUnwindSafe(|| handler(&report))).is_err() {
+                    eprintln!("Error reporting a panic: the panic handler panicked");
                 }
             }
-
-            HttpResponse::new(StatusCode::NotFound,"Not Found".to_string())
-        
+            None => eprintln!("Error handling {} {}: handler panicked: {}", report.method, report.path, report.message),
+        }
     }
 
+    /// Renders a response created from an `HttpError` with the error handler,
+    /// hiding the message of a server error if needed.
+    fn render_error(&self, mut response: HttpResponse) -> HttpResponse {
+        let Some(mut error) = response.error.take() else {
+            return response;
+        };
+        // A middleware may have changed the status since the error was created
+        error.status = response.status;
 
-    fn match_route_pattern(pattern:&str,uri:&str) -> Option<HashMap<String,String>> {
-        let pattern_parts: Vec<&str> = pattern.split('/').collect();
-        let uri_parts: Vec<&str> = uri.split('/').collect();
-
-
-        if pattern_parts.len() != uri_parts.len() {
-            return None;
+        let hide = !self.expose_internal_errors && error.is_server_error();
+        if hide {
+            eprintln!("Error handling request: {:?}", error);
+            error.message = error.status.reason_phrase().to_string();
+            error.details = None;
+        } else if self.error_handler.is_none() {
+            response.error = Some(error);
+            return response;
         }
 
-        let mut params = HashMap::new();
+        let mut rendered = match &self.error_handler {
+            Some(handler) => handler(&error),
+            None => (*error).clone().into_response(),
+        };
+        // The headers describing the body belong to the rendered one
+        rendered.keep_headers(response.headers);
+        rendered.error = Some(error);
+        rendered
+    }
 
-        for (pattern_part,uri_part) in pattern_parts.iter().zip(uri_parts.iter()) {
-            if pattern_part.starts_with('{') && pattern_part.ends_with('}') {
+    /// Calls the handler registered for the request.
+    /// 
+    /// Answers `OPTIONS` requests without a handler with the allowed methods,
+    /// returns 405 Method Not Allowed if the path exists but the method does not,
+    /// and 404 Not Found if the path does not exist.
+    fn route(&self, mut current_request: HttpRequest) -> HttpResponse {
+        let Some(route) = self.routes.find(current_request.raw_path(), &current_request.method) else {
+            return HttpError::not_found("Not Found").into_response();
+        };
 
-                let param_name = &pattern_part[1..pattern_part.len()-1];
-                params.insert(param_name.to_string(),uri_part.to_string());
-            }else if pattern_part != uri_part {
-                return None;
-            }
+        if let Some(handler) = route.handler(&current_request.method) {
+            current_request.path_params = route.params;
+            return handler(current_request);
+        }
+
+        let mut response = if current_request.method == "OPTIONS" {
+            HttpResponse::new(StatusCode::NoContent, "")
+        } else {
+            HttpError::new(StatusCode::MethodNotAllowed, "Method Not Allowed").into_response()
+        };
+        // The methods of the routes are not validated when they are added
+        if let Err(e) = response.headers.try_insert("Allow", route.allow()) {
+            eprintln!("Error listing the allowed methods: {}", e);
         }
-        Some(params)
+        response
     }
 
     /// Starts a server on a specified port
     /// 
-    /// 
+    /// Accepted connections are handed over to a pool of worker threads
+    /// (see [`App::set_workers`]), so a slow handler does not block other clients.
     /// 
     /// 
     /// # Arguments
@@ -462,133 +969,471 @@ impl App {
     /// ```
     /// 
     pub fn listen(&self,port: u16) {
-        if let Ok(listener) = TcpListener::bind(format!("localhost:{port}")) {
-            println!("Listening on a port {port}...");
-            loop {
-                let (mut socket,_) = listener.accept().unwrap();
-                self.process(&mut socket)
-            }
-        } else {
+        if self.listen_on(format!("localhost:{port}")).is_err() {
             panic!("Error occured");
         }
     }
 
-    fn process(&self, socket: &mut TcpStream) {
-    let mut buffer = [0; 4096];
-    let mut request_data = Vec::new();
-    let max_request_size = 8192; // Increased for body support
-    let mut total_bytes_read = 0;
+    /// Starts a server on a specified address.
+    /// 
+    /// Unlike [`App::listen`], any address can be used, e.g. `0.0.0.0:8080` inside a container,
+    /// `[::1]:8080` for IPv6 or port `0` for an ephemeral port.
+    /// The real address is printed once the server is listening.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `addr` - the address to bind
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the address cannot be bound.
+    /// 
+    /// # Examples
+    /// 
+    /// ```no_run
+    /// use http::{App, Respondable};
+    /// 
+    /// let mut app = App::new();
+    /// app.get("/".to_string(), |_| "hello world".into_response());
+    /// 
+    /// app.listen_on("0.0.0.0:8080").unwrap();
+    /// ```
+    pub fn listen_on<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        self.serve(listener)
+    }
 
-    // Read headers first
-    let headers_end = loop {
-        match socket.read(&mut buffer) {
-            Ok(0) => {
-                // Connection closed by client
-                return;
+    /// Serves connections from an already bound listener.
+    /// 
+    /// This allows binding the socket yourself, e.g. to learn the real address of an ephemeral port
+    /// before the server starts.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `listener` - a bound TCP listener
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the listener cannot be used.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        listener.set_nonblocking(false)?;
+        println!("Listening on {} with {} workers...", listener.local_addr()?, self.config.workers);
+        self.serve_until(listener, &AtomicBool::new(false));
+        Ok(())
+    }
+
+    /// Starts a server on a Unix domain socket at the specified path.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - the path of the socket file, which must not exist yet
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the socket cannot be bound.
+    #[cfg(unix)]
+    pub fn listen_unix<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let listener = UnixListener::bind(path)?;
+        self.serve_unix(listener)
+    }
+
+    /// Serves connections from an already bound Unix domain socket listener.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `listener` - a bound Unix domain socket listener
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the listener cannot be used.
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: UnixListener) -> io::Result<()> {
+        listener.set_nonblocking(false)?;
+        println!("Listening on {:?} with {} workers...", listener.local_addr()?, self.config.workers);
+        self.serve_until(listener, &AtomicBool::new(false));
+        Ok(())
+    }
+
+    /// Starts a server on a specified port in a background thread.
+    /// 
+    /// Unlike [`App::listen`], this method returns right away with a [`ServerHandle`]
+    /// which can be used to gracefully stop the server.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `port` - a designated port number
+    /// 
+    /// # Examples
+    /// 
+    /// ```no_run
+    /// use http::{App, Respondable};
+    /// 
+    /// let mut app = App::new();
+    /// app.get("/".to_string(), |_| "hello world".into_response());
+    /// 
+    /// let server = app.spawn(3000).unwrap();
+    /// server.shutdown();
+    /// server.join();
+    /// ```
+    pub fn spawn(self, port: u16) -> io::Result<ServerHandle> {
+        self.spawn_on(format!("localhost:{port}"))
+    }
+
+    /// Starts a server on a specified address in a background thread.
+    /// 
+    /// Use port `0` to get an ephemeral port, the real address is available
+    /// from [`ServerHandle::local_addr`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `addr` - the address to bind
+    /// 
+    /// # Examples
+    /// 
+    /// ```no_run
+    /// use http::{App, Respondable};
+    /// 
+    /// let mut app = App::new();
+    /// app.get("/".to_string(), |_| "hello world".into_response());
+    /// 
+    /// let server = app.spawn_on("127.0.0.1:0").unwrap();
+    /// println!("{}", server.local_addr().unwrap());
+    /// server.shutdown();
+    /// ```
+    pub fn spawn_on<A: ToSocketAddrs>(self, addr: A) -> io::Result<ServerHandle> {
+        let listener = TcpListener::bind(addr)?;
+        self.spawn_listener(listener)
+    }
+
+    /// Serves connections from an already bound listener in a background thread.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `listener` - a bound TCP listener
+    pub fn spawn_listener(self, listener: TcpListener) -> io::Result<ServerHandle> {
+        listener.set_nonblocking(false)?;
+        let local_addr = listener.local_addr()?;
+        Ok(self.spawn_server(listener, BoundAddr::Tcp(local_addr)))
+    }
+
+    /// Starts a server on a Unix domain socket in a background thread.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - the path of the socket file, which must not exist yet
+    #[cfg(unix)]
+    pub fn spawn_unix<P: AsRef<Path>>(self, path: P) -> io::Result<ServerHandle> {
+        let listener = UnixListener::bind(&path)?;
+        Ok(self.spawn_server(listener, BoundAddr::Unix(path.as_ref().to_path_buf())))
+    }
+
+    fn spawn_server<L>(self, listener: L, addr: BoundAddr) -> ServerHandle
+    where
+        L: Listener + Send + 'static,
+    {
+        let shutdown_timeout = self.config.shutdown_timeout;
+
+        let state = Arc::new(ServerState::default());
+        let server_state = state.clone();
+        let thread = thread::spawn(move || {
+            let guard = FinishGuard(server_state);
+            self.serve_until(listener, &guard.0.shutdown);
+        });
+
+        ServerHandle::new(state, thread, addr, shutdown_timeout)
+    }
+
+    /// Accepts connections and serves them from the worker pool until `shutdown` is set.
+    /// 
+    /// Returns after every accepted connection has been served.
+    fn serve_until<L: Listener>(&self, listener: L, shutdown: &AtomicBool) {
+        let (sender, receiver) = mpsc::channel::<L::Socket>();
+        let receiver = Mutex::new(receiver);
+
+        thread::scope(|scope| {
+            for _ in 0..self.config.workers {
+                scope.spawn(|| self.worker_loop(&receiver, shutdown));
             }
-            Ok(n) => {
-                request_data.extend_from_slice(&buffer[..n]);
-                total_bytes_read += n;
 
-                // Look for the end of headers (\r\n\r\n)
-                if let Some(pos) = Self::find_headers_end(&request_data) {
-                    break pos + 4; // +4 to include \r\n\r\n
+            loop {
+                let stream = listener.accept_socket();
+
+                // A connection made after the shutdown only wakes up the accept loop
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
                 }
 
-                // Safety: prevent reading too much data
-                if total_bytes_read >= max_request_size {
-                    eprintln!("Request too large, stopping read");
-                    return;
+                match stream {
+                    Ok(socket) => {
+                        if sender.send(socket).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => eprintln!("Error accepting connection: {:?}", e),
+                }
+            }
+
+            // Workers stop once the queue is empty
+            drop(sender);
+        });
+    }
+
+    /// Takes accepted connections off the shared queue until it is closed.
+    fn worker_loop<S: Socket>(&self, receiver: &Mutex<Receiver<S>>, shutdown: &AtomicBool) {
+        loop {
+            // The lock is released at the end of this statement, before processing.
+            let next = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+            match next {
+                Ok(socket) => {
+                    // Panics of handlers are caught with their request, this also covers
+                    // e.g. a streamed body panicking while it is written
+                    if panic::catch_unwind(AssertUnwindSafe(|| self.process(socket, shutdown))).is_err() {
+                        eprintln!("Error serving connection: a panic occurred, the connection was closed");
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Waits for the first request of a connection, or the next request on a persistent connection.
+    /// 
+    /// Returns `false` if the connection should be closed instead, because it was closed by the client,
+    /// it has been idle for longer than the keep-alive timeout or the server is shutting down.
+    /// The wait is cut into short slices, so an idle connection does not hold its worker
+    /// for longer than the keep-alive timeout, nor delay a shutdown.
+    /// A request which was already sent, e.g. pipelined or queued before the shutdown, is still served.
+    fn wait_for_next_request<S: Socket>(&self, connection: &mut Connection<S>, shutdown: &AtomicBool) -> bool {
+        let deadline = Instant::now() + self.config.keep_alive_timeout;
+
+        loop {
+            if shutdown.load(Ordering::SeqCst) {
+                if connection.get_ref().set_read_timeout(Some(SHUTDOWN_READ_GRACE)).is_err() {
+                    return false;
                 }
+                return matches!(connection.wait_for_data(), Ok(true));
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            if connection.get_ref().set_read_timeout(Some(remaining.min(SHUTDOWN_POLL_INTERVAL))).is_err() {
+                return false;
+            }
+
+            match connection.wait_for_data() {
+                Ok(has_data) => return has_data,
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Serves requests from a single connection until it is closed.
+    /// 
+    /// HTTP/1.1 connections are kept open between requests unless the client sends
+    /// `Connection: close`, HTTP/1.0 connections are kept open only with `Connection: keep-alive`.
+    /// Idle connections are closed after the keep-alive timeout, and every connection
+    /// is closed after serving the maximum number of requests.
+    /// When the server is shutting down, the connection is closed after the requests already sent.
+    /// 
+    /// Requests exceeding the limits of the [`ServerConfig`] are answered with an error status
+    /// and the connection is closed.
+    fn process<S: Socket>(&self, socket: S, shutdown: &AtomicBool) {
+    let config = &self.config;
+    let mut connection = Connection::new(socket);
+    let mut served = 0;
+
+    if connection.get_ref().set_write_timeout(config.write_timeout).is_err() {
+        return;
+    }
+
+    loop {
+        // An idle connection is given the keep-alive timeout, the read timeout applies once a request has started
+        if !self.wait_for_next_request(&mut connection, shutdown) {
+            return;
+        }
+
+        if connection.get_ref().set_read_timeout(config.read_timeout).is_err() {
+            return;
+        }
+
+        let head = match connection.read_head(config.max_header_bytes) {
+            Ok(head) => head,
+            Err(e) => {
+                Self::reject_read_error(&mut connection, e);
+                return;
             }
+        };
+
+        // Parse headers
+        let Ok(headers_str) = std::str::from_utf8(&head) else {
+            Self::reject(&mut connection, StatusCode::BadRequest, "Invalid UTF-8 in request headers");
+            return;
+        };
+
+        let mut request = match HttpRequest::parse(headers_str) {
+            Ok(request) => request,
             Err(e) => {
-                eprintln!("Error reading from socket: {:?}", e);
+                Self::reject(&mut connection, StatusCode::BadRequest, &e.to_string());
                 return;
             }
+        };
+
+        // Content-Length from headers tells how much body to read
+        let content_length = request.content_length;
+        if content_length > config.max_body_bytes {
+            Self::reject_read_error(&mut connection, ReadError::BodyTooLarge);
+            return;
         }
-    };
 
-    // Parse headers
-    if let Ok(headers_str) = std::str::from_utf8(&request_data[..headers_end]) {
-        match HttpRequest::parse(headers_str) {
-            Ok(mut request) => {
-                // Parse Content-Length from headers to know how much body to read
-                let content_length = Self::get_content_length(headers_str);
-                
-                // Read the body if there is one
-                if content_length > 0 {
-                    let mut body = Vec::new();
-                    let remaining_bytes = request_data.len() - headers_end;
-                    
-                    // Add any body data already read
-                    if remaining_bytes > 0 {
-                        body.extend_from_slice(&request_data[headers_end..]);
-                    }
+        // A proxy in front of the server may frame the body by the other header,
+        // and see a second request in it (RFC 9112, section 6.3)
+        if request.headers.contains_key("Transfer-Encoding") && request.headers.contains_key("Content-Length") {
+            Self::reject(&mut connection, StatusCode::BadRequest, "Both Transfer-Encoding and Content-Length are set");
+            return;
+        }
+
+        // Read the body if there is one.
+        let body = if request.headers.contains_key("Transfer-Encoding") {
+            let chunked = request
+                .headers
+                .get_all("Transfer-Encoding")
+                .flat_map(|v| v.split(','))
+                .last()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+            if !chunked {
+                Self::reject(&mut connection, StatusCode::BadRequest, "Unsupported transfer encoding");
+                return;
+            }
 
-                    // Read the rest of the body
-                    let mut bytes_to_read = content_length.saturating_sub(remaining_bytes);
-                    while bytes_to_read > 0 {
-                        match socket.read(&mut buffer) {
-                            Ok(0) => {
-                                // Connection closed before reading full body
-                                eprintln!("Connection closed before reading full body");
-                                break;
-                            }
-                            Ok(n) => {
-                                let to_copy = std::cmp::min(n, bytes_to_read);
-                                body.extend_from_slice(&buffer[..to_copy]);
-                                bytes_to_read -= to_copy;
-                            }
-                            Err(e) => {
-                                eprintln!("Error reading body from socket: {:?}", e);
-                                return;
-                            }
+            match connection.read_chunked_body(config.max_body_bytes) {
+                Ok((body, trailers)) => {
+                    for (name, value) in trailers {
+                        if let Err(e) = request.headers.try_append(name, value) {
+                            Self::reject(&mut connection, StatusCode::BadRequest, &format!("Invalid trailer: {}", e));
+                            return;
                         }
                     }
-                    
-                    // Convert body to string
-                    if let Ok(body_str) = std::str::from_utf8(&body) {
-                        request.body = body_str.to_string();
-                    } else {
-                        eprintln!("Invalid UTF-8 in request body");
-                    }
+                    Some(body)
+                }
+                Err(e) => {
+                    Self::reject_read_error(&mut connection, e);
+                    return;
                 }
-
-                // Handle the request with body
-                let response = self.handle_request(request);
-                socket.write_all(response.to_string().as_bytes()).unwrap();
-                socket.flush().unwrap();
             }
-            Err(e) => {
-                eprintln!("Error parsing request: {:?}", e);
+        } else if content_length > 0 {
+            match connection.read_body(content_length) {
+                Ok(body) => Some(body),
+                Err(e) => {
+                    Self::reject_read_error(&mut connection, e);
+                    return;
+                }
             }
+        } else {
+            None
+        };
+
+        if let Some(body) = body {
+            request.content_length = body.len();
+            request.body = body;
+        }
+
+        served += 1;
+        let mut keep_alive = request.keep_alive() && served < config.max_keep_alive_requests;
+        let http_1_0 = request.version == "HTTP/1.0";
+        let head_only = request.method == "HEAD";
+
+        // Handle the request with body
+        let mut response = self.handle_request(request);
+
+        // Pipelined requests already read are served before closing
+        if shutdown.load(Ordering::SeqCst) && !connection.has_buffered_data() {
+            keep_alive = false;
+        }
+
+        // A handler may close the connection itself
+        if response.headers.get_all("Connection").any(|v| v.eq_ignore_ascii_case("close")) {
+            keep_alive = false;
+        }
+        response.headers.remove("Connection");
+        // HTTP/1.0 clients cannot read chunked bodies, the end of the stream is marked by closing the connection
+        if http_1_0 && response.body.is_stream() && !head_only {
+            keep_alive = false;
+        }
+        if !keep_alive {
+            response.headers.insert("Connection", "close");
+        } else if http_1_0 {
+            response.headers.insert("Connection", "keep-alive");
+        }
+
+        if let Err(e) = response.write(connection.get_mut(), !http_1_0, head_only) {
+            eprintln!("Error writing response: {:?}", e);
+            return;
+        }
+
+        if !keep_alive {
+            return;
         }
-    } else {
-        eprintln!("Invalid UTF-8 in request headers");
     }
 }
 
-// Helper function to find the end of HTTP headers
-fn find_headers_end(data: &[u8]) -> Option<usize> {
-    for i in 0..data.len().saturating_sub(3) {
-        if data[i] == b'\r' && 
-           data[i + 1] == b'\n' && 
-           data[i + 2] == b'\r' && 
-           data[i + 3] == b'\n' {
-            return Some(i);
-        }
+/// Sends an error response and marks the connection to be closed.
+fn reject<S: Socket>(connection: &mut Connection<S>, status: StatusCode, message: &str) {
+    let mut response = HttpResponse::new(status, message.to_string());
+    response.headers.insert("Connection", "close");
+    if let Err(e) = response.write_to(connection.get_mut()) {
+        eprintln!("Error writing response: {:?}", e);
     }
-    None
 }
 
-// Helper function to extract Content-Length from headers
-fn get_content_length(headers: &str) -> usize {
-    for line in headers.lines() {
-        if line.to_lowercase().starts_with("content-length:") {
-            return line[15..].trim().parse().unwrap_or(0);
+/// Answers a request which could not be read with a matching error status.
+/// 
+/// The connection is closed without a response if the client is already gone.
+fn reject_read_error<S: Socket>(connection: &mut Connection<S>, e: ReadError) {
+    let status = match &e {
+        ReadError::HeadTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
+        ReadError::BodyTooLarge => StatusCode::ContentTooLarge,
+        ReadError::Malformed(_) => StatusCode::BadRequest,
+        e if e.is_timeout() => StatusCode::RequestTimeout,
+        ReadError::Closed => return,
+        ReadError::Io(_) => {
+            eprintln!("{}", e);
+            return;
         }
+    };
+    Self::reject(connection, status, &e.to_string());
+}
+
+// Helper function to guess the Content-Type of a static file from its extension
+fn get_content_type(path: &std::path::Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
     }
-    0
 }
 
 }
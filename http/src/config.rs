@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+/// Settings of the server started by an `App`.
+///
+/// Every limit is enforced with a proper status response:
+/// a too large head gets `431 Request Header Fields Too Large`,
+/// a too large body gets `413 Content Too Large`,
+/// and a client which is too slow to send its request gets `408 Request Timeout`.
+///
+/// # Examples
+///
+/// ```
+/// use http::{App, ServerConfig};
+/// use std::time::Duration;
+///
+/// let mut app = App::new();
+/// app.set_config(ServerConfig {
+///     max_body_bytes: 16 * 1024 * 1024,
+///     read_timeout: Some(Duration::from_secs(10)),
+///     ..ServerConfig::default()
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// The number of connections served in parallel.
+    ///
+    /// A connection keeps its worker while it waits for its next request, up to the keep-alive
+    /// timeout, and a browser opens about six of them. Fewer workers than open connections
+    /// make the other clients wait, more workers cost a thread each.
+    pub workers: usize,
+    /// The maximum size of the request line and headers in bytes.
+    pub max_header_bytes: usize,
+    /// The maximum size of a request body in bytes.
+    pub max_body_bytes: usize,
+    /// The timeout of every read while a request is being received, `None` means no timeout.
+    pub read_timeout: Option<Duration>,
+    /// The timeout of every write while a response is being sent, `None` means no timeout.
+    pub write_timeout: Option<Duration>,
+    /// How long an idle connection is kept open, before its first request or between requests.
+    pub keep_alive_timeout: Duration,
+    /// The maximum number of requests served on a single connection.
+    pub max_keep_alive_requests: usize,
+    /// How long a graceful shutdown waits for in-flight requests to finish.
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    /// Creates the default configuration:
+    ///
+    /// * 64 workers,
+    /// * 8 KiB of headers and 2 MiB of body,
+    /// * 30 seconds read and write timeouts,
+    /// * 5 seconds keep-alive timeout and 100 requests per connection,
+    /// * 30 seconds shutdown timeout.
+    fn default() -> Self {
+        Self {
+            workers: 64,
+            max_header_bytes: 8 * 1024,
+            max_body_bytes: 2 * 1024 * 1024,
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            keep_alive_timeout: Duration::from_secs(5),
+            max_keep_alive_requests: 100,
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
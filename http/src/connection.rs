@@ -0,0 +1,300 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Size of a single read from the socket.
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Maximum length of a chunk-size line or a trailer line in a chunked body.
+const MAX_LINE_SIZE: usize = 4096;
+
+/// Trailer headers sent after the last chunk of a chunked body.
+pub type Trailers = Vec<(String, String)>;
+
+/// Errors which can happen while reading a request from a connection.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The client closed the connection before sending a new request.
+    Closed,
+    /// The request head is larger than allowed.
+    HeadTooLarge,
+    /// The request body is larger than allowed.
+    BodyTooLarge,
+    /// The request is not valid HTTP, e.g. a malformed chunk size.
+    Malformed(String),
+    /// The underlying socket failed (this includes read timeouts).
+    Io(io::Error),
+}
+
+impl ReadError {
+    /// Returns `true` if the error was caused by a read timeout.
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            ReadError::Io(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+        )
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Closed => write!(f, "Connection closed by client"),
+            ReadError::HeadTooLarge => write!(f, "Request headers too large"),
+            ReadError::BodyTooLarge => write!(f, "Request body too large"),
+            ReadError::Malformed(m) => write!(f, "Malformed request: {}", m),
+            ReadError::Io(e) => write!(f, "Error reading from socket: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+/// A client socket requests can be served from.
+pub trait Socket: Read + Write + Send {
+    /// Sets the timeout of every read from the socket, `None` means reads block forever.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Sets the timeout of every write to the socket, `None` means writes block forever.
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Socket for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// A listener accepting client sockets.
+pub trait Listener {
+    /// The type of the accepted sockets.
+    type Socket: Socket;
+
+    /// Blocks until a new client connects.
+    fn accept_socket(&self) -> io::Result<Self::Socket>;
+}
+
+impl Listener for TcpListener {
+    type Socket = TcpStream;
+
+    fn accept_socket(&self) -> io::Result<TcpStream> {
+        self.accept().map(|(socket, _)| socket)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Socket = UnixStream;
+
+    fn accept_socket(&self) -> io::Result<UnixStream> {
+        self.accept().map(|(socket, _)| socket)
+    }
+}
+
+/// A buffered client connection.
+///
+/// Bytes read past the end of a request are kept in the buffer,
+/// so several requests can be read one after another from the same socket.
+pub struct Connection<S> {
+    stream: S,
+    buffer: Vec<u8>,
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Wraps a stream into a connection with an empty buffer.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Returns `true` if data has been read from the stream but not consumed yet.
+    pub fn has_buffered_data(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Waits until some data is available to read.
+    ///
+    /// Returns `false` if the stream was closed.
+    pub fn wait_for_data(&mut self) -> io::Result<bool> {
+        if !self.buffer.is_empty() {
+            return Ok(true);
+        }
+        Ok(self.fill()? > 0)
+    }
+
+    /// Reads the request head, up to and including the empty line.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - the maximum allowed size of the head in bytes
+    pub fn read_head(&mut self, max_size: usize) -> Result<Vec<u8>, ReadError> {
+        loop {
+            if let Some(pos) = find_headers_end(&self.buffer) {
+                let head_end = pos + 4; // +4 to include \r\n\r\n
+                if head_end > max_size {
+                    return Err(ReadError::HeadTooLarge);
+                }
+                return Ok(self.buffer.drain(..head_end).collect());
+            }
+
+            // Safety: prevent reading too much data
+            if self.buffer.len() >= max_size {
+                return Err(ReadError::HeadTooLarge);
+            }
+
+            if self.fill()? == 0 {
+                return Err(if self.buffer.is_empty() {
+                    ReadError::Closed
+                } else {
+                    ReadError::Io(io::ErrorKind::UnexpectedEof.into())
+                });
+            }
+        }
+    }
+
+    /// Reads exactly `len` bytes of the request body.
+    pub fn read_body(&mut self, len: usize) -> Result<Vec<u8>, ReadError> {
+        while self.buffer.len() < len {
+            if self.fill()? == 0 {
+                return Err(ReadError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        Ok(self.buffer.drain(..len).collect())
+    }
+
+    /// Reads a body sent with `Transfer-Encoding: chunked`.
+    ///
+    /// Returns the decoded body together with the trailer headers sent after the last chunk.
+    /// Chunk extensions are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - the maximum allowed size of the decoded body in bytes
+    pub fn read_chunked_body(&mut self, max_size: usize) -> Result<(Vec<u8>, Trailers), ReadError> {
+        let mut body = Vec::new();
+
+        loop {
+            let line = self.read_line()?;
+            let size = parse_chunk_size(&line)?;
+            if size == 0 {
+                break;
+            }
+            if size > max_size - body.len() {
+                return Err(ReadError::BodyTooLarge);
+            }
+
+            body.extend(self.read_body(size)?);
+            if !self.read_line()?.is_empty() {
+                return Err(ReadError::Malformed("Missing CRLF after chunk data".to_string()));
+            }
+        }
+
+        let mut trailers = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                break; // End of trailers
+            }
+            let line = std::str::from_utf8(&line)
+                .map_err(|_| ReadError::Malformed("Invalid UTF-8 in trailer".to_string()))?;
+            let colon_pos = line
+                .find(':')
+                .ok_or_else(|| ReadError::Malformed(format!("Invalid trailer: {}", line)))?;
+            trailers.push((line[..colon_pos].trim().to_string(), line[colon_pos + 1..].trim().to_string()));
+        }
+
+        Ok((body, trailers))
+    }
+
+    /// Reads a single line terminated by CRLF, without the terminator.
+    fn read_line(&mut self) -> Result<Vec<u8>, ReadError> {
+        loop {
+            if let Some(pos) = self.buffer.windows(2).position(|window| window == b"\r\n") {
+                let mut line: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+                line.truncate(pos);
+                return Ok(line);
+            }
+
+            if self.buffer.len() > MAX_LINE_SIZE {
+                return Err(ReadError::Malformed("Line too long in chunked body".to_string()));
+            }
+
+            if self.fill()? == 0 {
+                return Err(ReadError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+    }
+
+    /// Reads the next chunk from the stream into the buffer.
+    ///
+    /// Returns the number of bytes read, `0` means the stream is closed.
+    fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Helper function to find the end of HTTP headers
+fn find_headers_end(data: &[u8]) -> Option<usize> {
+    data.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+// Helper function to parse a chunk-size line, ignoring chunk extensions
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ReadError> {
+    let malformed = || ReadError::Malformed(format!("Invalid chunk size: {}", String::from_utf8_lossy(line)));
+
+    let line = std::str::from_utf8(line).map_err(|_| malformed())?;
+    let size = line.split(';').next().unwrap_or("").trim();
+
+    if size.is_empty() || size.len() > 16 || !size.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(malformed());
+    }
+    usize::from_str_radix(size, 16).map_err(|_| malformed())
+}
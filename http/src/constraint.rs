@@ -0,0 +1,416 @@
+use std::{iter::Peekable, str::{Chars, FromStr}};
+
+/// A condition a path parameter must meet for its route to match.
+///
+/// Written after the name of a parameter, e.g. `{id:u64}` or `{slug:[a-z0-9-]+}`.
+pub(crate) enum Constraint {
+    /// The value must parse as a primitive type, e.g. `u64`.
+    Type(&'static str, Check),
+    /// The whole value must match a regular expression.
+    Regex(String, Regex),
+}
+
+/// Returns `true` if a value meets a type constraint.
+type Check = fn(&str) -> bool;
+
+fn parses<T: FromStr>(value: &str) -> bool {
+    value.parse::<T>().is_ok()
+}
+
+/// The types which can be used as constraints.
+const TYPES: &[(&str, Check)] = &[
+    ("u8", parses::<u8>),
+    ("u16", parses::<u16>),
+    ("u32", parses::<u32>),
+    ("u64", parses::<u64>),
+    ("u128", parses::<u128>),
+    ("usize", parses::<usize>),
+    ("i8", parses::<i8>),
+    ("i16", parses::<i16>),
+    ("i32", parses::<i32>),
+    ("i64", parses::<i64>),
+    ("i128", parses::<i128>),
+    ("isize", parses::<isize>),
+    ("f32", parses::<f32>),
+    ("f64", parses::<f64>),
+    ("bool", parses::<bool>),
+];
+
+impl Constraint {
+    /// Parses a constraint, which is either a type name or a regular expression.
+    pub(crate) fn parse(source: &str) -> Result<Self, String> {
+        match TYPES.iter().find(|(name, _)| *name == source) {
+            Some((name, check)) => Ok(Constraint::Type(name, *check)),
+            None => Ok(Constraint::Regex(source.to_string(), Regex::new(source)?)),
+        }
+    }
+
+    /// Returns `true` if the value meets the constraint.
+    pub(crate) fn check(&self, value: &str) -> bool {
+        match self {
+            Constraint::Type(_, check) => check(value),
+            Constraint::Regex(_, regex) => regex.is_match(value),
+        }
+    }
+
+    /// Returns the constraint as it was written.
+    pub(crate) fn source(&self) -> &str {
+        match self {
+            Constraint::Type(name, _) => name,
+            Constraint::Regex(source, _) => source,
+        }
+    }
+}
+
+/// A regular expression matched against a whole string.
+///
+/// Supports a small subset of the usual syntax, which is enough for path segments:
+/// literals, `.`, classes like `[a-z_]` or `[^/]`, the escapes `\d`, `\w`, `\s` and their
+/// negations, groups, alternation with `|`, and the quantifiers `*`, `+`, `?` and `{n,m}`.
+///
+/// The expression is compiled into a program, which is run on every character at once
+/// without backtracking (a Pike VM), so matching takes linear time in the length of the value
+/// and constant stack space, whatever the expression.
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+}
+
+/// The largest program an expression may compile to, counted repetitions are expanded.
+const MAX_PROGRAM_LEN: usize = 10_000;
+
+/// An instruction of a compiled expression.
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    /// Continues at both positions.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+type Sequence = Vec<Piece>;
+
+/// An atom with its quantifier.
+struct Piece {
+    atom: Atom,
+    min: usize,
+    max: Option<usize>,
+}
+
+enum Atom {
+    Char(char),
+    Any,
+    Class(Class),
+    Group(Vec<Sequence>),
+}
+
+#[derive(Clone)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+
+    /// Returns the class of an escape like `\d`, `None` for an escaped literal.
+    fn from_escape(c: char) -> Option<Class> {
+        let ranges = match c.to_ascii_lowercase() {
+            'd' => vec![('0', '9')],
+            'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+            's' => vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r'), ('\x0b', '\x0c')],
+            _ => return None,
+        };
+        Some(Class { ranges, negated: c.is_ascii_uppercase() })
+    }
+}
+
+impl Regex {
+    /// Compiles a regular expression.
+    pub(crate) fn new(source: &str) -> Result<Self, String> {
+        let mut chars = source.chars().peekable();
+
+        // The expression is always matched against the whole value
+        if chars.peek() == Some(&'^') {
+            chars.next();
+        }
+        let alternatives = parse_alternatives(&mut chars)?;
+        if let Some(c) = chars.next() {
+            return Err(format!("Unexpected `{}` in regular expression `{}`", c, source));
+        }
+
+        let mut program = Vec::new();
+        compile_alternatives(&alternatives, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    /// Returns `true` if the whole value matches.
+    pub(crate) fn is_match(&self, value: &str) -> bool {
+        // `seen[pc] == step` once the instruction is in the list of the current step
+        let mut seen = vec![usize::MAX; self.program.len()];
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        self.add_thread(&mut current, &mut seen, 0, 0);
+
+        for (step, c) in value.chars().enumerate() {
+            if current.is_empty() {
+                return false;
+            }
+            for &pc in &current {
+                let matches = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(c),
+                    _ => false,
+                };
+                if matches {
+                    self.add_thread(&mut next, &mut seen, step + 1, pc + 1);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        current.iter().any(|&pc| matches!(self.program[pc], Inst::Match))
+    }
+
+    /// Adds the instructions reachable from `pc` without reading a character to the list.
+    fn add_thread(&self, list: &mut Vec<usize>, seen: &mut [usize], step: usize, pc: usize) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            // Also stops the loops of repeated empty matches, e.g. `(a*)*`
+            if seen[pc] == step {
+                continue;
+            }
+            seen[pc] = step;
+            match self.program[pc] {
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => stack.extend([second, first]),
+                _ => list.push(pc),
+            }
+        }
+    }
+}
+
+fn parse_alternatives(chars: &mut Peekable<Chars<'_>>) -> Result<Vec<Sequence>, String> {
+    let mut alternatives = vec![parse_sequence(chars)?];
+    while chars.peek() == Some(&'|') {
+        chars.next();
+        alternatives.push(parse_sequence(chars)?);
+    }
+    Ok(alternatives)
+}
+
+fn parse_sequence(chars: &mut Peekable<Chars<'_>>) -> Result<Sequence, String> {
+    let mut sequence = Vec::new();
+
+    while let Some(&c) = chars.peek() {
+        let atom = match c {
+            '|' | ')' => break,
+            '$' => {
+                chars.next();
+                if chars.peek().is_some_and(|&c| c != '|' && c != ')') {
+                    return Err("`$` is only supported at the end".to_string());
+                }
+                continue;
+            }
+            '*' | '+' | '?' | '{' => return Err(format!("Nothing to repeat before `{}`", c)),
+            '(' => {
+                chars.next();
+                // Groups never capture, so `(?:` is the same as `(`
+                if chars.peek() == Some(&'?') {
+                    chars.next();
+                    if chars.next() != Some(':') {
+                        return Err("Only `(?:` groups are supported".to_string());
+                    }
+                }
+                let group = parse_alternatives(chars)?;
+                if chars.next() != Some(')') {
+                    return Err("Missing `)`".to_string());
+                }
+                Atom::Group(group)
+            }
+            '[' => {
+                chars.next();
+                Atom::Class(parse_class(chars)?)
+            }
+            '.' => {
+                chars.next();
+                Atom::Any
+            }
+            '\\' => {
+                chars.next();
+                let escaped = chars.next().ok_or("Trailing `\\`")?;
+                Class::from_escape(escaped).map_or(Atom::Char(escaped), Atom::Class)
+            }
+            c => {
+                chars.next();
+                Atom::Char(c)
+            }
+        };
+
+        let (min, max) = parse_quantifier(chars)?;
+        sequence.push(Piece { atom, min, max });
+    }
+
+    Ok(sequence)
+}
+
+fn parse_quantifier(chars: &mut Peekable<Chars<'_>>) -> Result<(usize, Option<usize>), String> {
+    let quantifier = match chars.peek() {
+        Some('*') => (0, None),
+        Some('+') => (1, None),
+        Some('?') => (0, Some(1)),
+        Some('{') => {
+            chars.next();
+            let mut inner = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => inner.push(c),
+                    None => return Err("Missing `}`".to_string()),
+                }
+            }
+            let number = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("Invalid repetition `{{{}}}`", inner));
+            let quantifier = match inner.split_once(',') {
+                None => (number(&inner)?, Some(number(&inner)?)),
+                Some((min, "")) => (number(min)?, None),
+                Some((min, max)) => (number(min)?, Some(number(max)?)),
+            };
+            if quantifier.1.is_some_and(|max| max < quantifier.0) {
+                return Err(format!("Invalid repetition `{{{}}}`", inner));
+            }
+            // A lazy quantifier matches the same whole strings
+            if chars.peek() == Some(&'?') {
+                chars.next();
+            }
+            return Ok(quantifier);
+        }
+        _ => return Ok((1, Some(1))),
+    };
+
+    chars.next();
+    if chars.peek() == Some(&'?') {
+        chars.next();
+    }
+    Ok(quantifier)
+}
+
+fn parse_class(chars: &mut Peekable<Chars<'_>>) -> Result<Class, String> {
+    let mut class = Class { ranges: Vec::new(), negated: false };
+    if chars.peek() == Some(&'^') {
+        chars.next();
+        class.negated = true;
+    }
+
+    let mut first = true;
+    loop {
+        let c = chars.next().ok_or("Missing `]`")?;
+        let lo = match c {
+            // A `]` right after the opening bracket is a literal
+            ']' if !first => return Ok(class),
+            '\\' => {
+                let escaped = chars.next().ok_or("Trailing `\\`")?;
+                if let Some(escape) = Class::from_escape(escaped) {
+                    if escape.negated {
+                        return Err("Negated escapes are not supported in classes".to_string());
+                    }
+                    class.ranges.extend(escape.ranges);
+                    first = false;
+                    continue;
+                }
+                escaped
+            }
+            c => c,
+        };
+        first = false;
+
+        let mut lookahead = chars.clone();
+        if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|&c| c != ']') {
+            chars.next();
+            let hi = match chars.next() {
+                Some('\\') => chars.next().ok_or("Trailing `\\`")?,
+                Some(c) => c,
+                None => return Err("Missing `]`".to_string()),
+            };
+            if hi < lo {
+                return Err(format!("Invalid range `{}-{}`", lo, hi));
+            }
+            class.ranges.push((lo, hi));
+        } else {
+            class.ranges.push((lo, lo));
+        }
+    }
+}
+
+fn compile_alternatives(alternatives: &[Sequence], program: &mut Vec<Inst>) -> Result<(), String> {
+    let (last, rest) = alternatives.split_last().expect("an expression has at least one alternative");
+    let mut jumps = Vec::new();
+    for sequence in rest {
+        let split = program.len();
+        program.push(Inst::Split(split + 1, 0));
+        compile_sequence(sequence, program)?;
+        jumps.push(program.len());
+        program.push(Inst::Jump(0));
+        program[split] = Inst::Split(split + 1, program.len());
+    }
+    compile_sequence(last, program)?;
+
+    let end = program.len();
+    for jump in jumps {
+        program[jump] = Inst::Jump(end);
+    }
+    Ok(())
+}
+
+fn compile_sequence(sequence: &[Piece], program: &mut Vec<Inst>) -> Result<(), String> {
+    sequence.iter().try_for_each(|piece| compile_piece(piece, program))
+}
+
+/// Compiles a piece as its required repetitions followed by the optional ones.
+fn compile_piece(piece: &Piece, program: &mut Vec<Inst>) -> Result<(), String> {
+    for _ in 0..piece.min {
+        compile_atom(&piece.atom, program)?;
+    }
+
+    match piece.max {
+        None => {
+            let split = program.len();
+            program.push(Inst::Split(split + 1, 0));
+            compile_atom(&piece.atom, program)?;
+            program.push(Inst::Jump(split));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+        Some(max) => {
+            let mut splits = Vec::new();
+            for _ in piece.min..max {
+                splits.push(program.len());
+                program.push(Inst::Split(program.len() + 1, 0));
+                compile_atom(&piece.atom, program)?;
+            }
+            let end = program.len();
+            for split in splits {
+                program[split] = Inst::Split(split + 1, end);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn compile_atom(atom: &Atom, program: &mut Vec<Inst>) -> Result<(), String> {
+    match atom {
+        Atom::Char(c) => program.push(Inst::Char(*c)),
+        Atom::Any => program.push(Inst::Any),
+        Atom::Class(class) => program.push(Inst::Class(class.clone())),
+        Atom::Group(alternatives) => compile_alternatives(alternatives, program)?,
+    }
+
+    if program.len() > MAX_PROGRAM_LEN {
+        return Err("Regular expression is too large".to_string());
+    }
+    Ok(())
+}
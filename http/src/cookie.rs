@@ -0,0 +1,339 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::headers::{is_valid_name, HeaderMap};
+
+/// Errors which can happen when creating a cookie.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CookieError {
+    /// The name is empty or contains characters which are not allowed in a cookie name.
+    InvalidName(String),
+    /// The value contains characters which are not allowed in a cookie value, e.g. `;` or spaces.
+    InvalidValue(String),
+}
+
+impl std::fmt::Display for CookieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CookieError::InvalidName(n) => write!(f, "Invalid cookie name: {:?}", n),
+            CookieError::InvalidValue(v) => write!(f, "Invalid cookie value: {:?}", v),
+        }
+    }
+}
+
+impl std::error::Error for CookieError {}
+
+/// The cookies sent by a client in its `Cookie` headers.
+///
+/// # Examples
+///
+/// ```
+/// use http::HttpRequest;
+///
+/// let req = HttpRequest::parse("GET / HTTP/1.1\r\nCookie: theme=dark; lang=en\r\n\r\n").unwrap();
+/// let cookies = req.cookies();
+///
+/// assert_eq!(cookies.get("theme"), Some("dark"));
+/// assert_eq!(cookies.get("session"), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+impl CookieJar {
+    /// Creates an empty jar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the cookies of every `Cookie` header, malformed pairs are skipped.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let cookies = headers
+            .get_all("Cookie")
+            .flat_map(|header| header.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                let value = value.trim();
+                // A value may be wrapped in double quotes
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        Self { cookies }
+    }
+
+    /// Returns the value of a cookie.
+    ///
+    /// If a client sent a name several times, the first value is returned.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns `true` if the cookie is present.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Returns an iterator over all cookies, in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Returns the number of cookies.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Returns `true` if there are no cookies.
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Browsers accept `SameSite=None` only together with `Secure`.
+    None,
+}
+
+impl std::fmt::Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+/// A cookie sent to the client in a `Set-Cookie` header.
+///
+/// Formatting a cookie with `Display` gives the value of the header.
+///
+/// # Examples
+///
+/// ```
+/// use http::{Cookie, HttpResponse, SameSite, StatusCode};
+/// use std::time::Duration;
+///
+/// let mut response = HttpResponse::new(StatusCode::Ok, "Logged in");
+/// response.set_cookie(
+///     Cookie::new("session", "abc123")
+///         .path("/")
+///         .max_age(Duration::from_secs(3600))
+///         .http_only(true)
+///         .same_site(SameSite::Lax),
+/// );
+/// response.set_cookie(Cookie::new("theme", "dark"));
+///
+/// assert_eq!(
+///     response.headers.get_all("Set-Cookie").collect::<Vec<_>>(),
+///     vec!["session=abc123; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax", "theme=dark"]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<SystemTime>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Creates a cookie without attributes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or the value is invalid, see [`Cookie::try_new`].
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        match Self::try_new(name, value) {
+            Ok(cookie) => cookie,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a cookie without attributes.
+    ///
+    /// Fails if the name is not a valid token or the value contains
+    /// spaces, `"`, `,`, `;`, `\` or control characters (RFC 6265, section 4.1.1).
+    pub fn try_new(name: impl Into<String>, value: impl Into<String>) -> Result<Self, CookieError> {
+        let name = name.into();
+        let value = value.into();
+
+        if !is_valid_name(&name) {
+            return Err(CookieError::InvalidName(name));
+        }
+        if !is_valid_value(&value) {
+            return Err(CookieError::InvalidValue(value));
+        }
+
+        Ok(Self {
+            name,
+            value,
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        })
+    }
+
+    /// Creates a cookie which makes the client delete the cookie with this name.
+    ///
+    /// The `Path` and `Domain` must match the ones the cookie was set with.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "").expires(UNIX_EPOCH).max_age(Duration::ZERO)
+    }
+
+    /// Returns the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Sets the `Path` attribute.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the path contains `;` or control characters.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(checked_attribute("Path", path.into()));
+        self
+    }
+
+    /// Sets the `Domain` attribute.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the domain contains `;` or control characters.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(checked_attribute("Domain", domain.into()));
+        self
+    }
+
+    /// Sets the `Expires` attribute.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Sets the `Max-Age` attribute, which takes precedence over `Expires`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets the `Secure` attribute, so the cookie is only sent over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Sets the `HttpOnly` attribute, so the cookie is not readable by scripts.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Sets the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+}
+
+impl std::fmt::Display for Cookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a time as an HTTP date, e.g. `Wed, 21 Oct 2015 07:28:00 GMT` (RFC 9110, section 5.6.7).
+///
+/// Times before 1970 are formatted as the Unix epoch.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = secs / 86400;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // Converts days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+fn checked_attribute(attribute: &str, value: String) -> String {
+    if value.contains(';') || value.chars().any(|c| c.is_control()) {
+        panic!("Invalid cookie {}: {:?}", attribute, value);
+    }
+    value
+}
+
+/// Returns `true` if the value consists of cookie-octets (RFC 6265, section 4.1.1).
+fn is_valid_value(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|b| b == 0x21 || (0x23..=0x2b).contains(&b) || (0x2d..=0x3a).contains(&b) || (0x3c..=0x5b).contains(&b) || (0x5d..=0x7e).contains(&b))
+}
@@ -0,0 +1,154 @@
+use std::io;
+
+use crate::jsonable::JsonValue;
+use crate::multipart::MultipartError;
+use crate::server::{HttpResponse, Respondable, StatusCode};
+use crate::urlencoded::FormError;
+
+/// An error which is sent to the client as a response.
+///
+/// Handlers return it in a `Result`, which is respondable for any error type
+/// converting into an `HttpError`. `String` and `&str` errors become
+/// `500 Internal Server Error`, errors of the request helpers like [`FormError`]
+/// get a matching client error status.
+///
+/// By default the message is sent as a plain text body. An `App` renders every error
+/// with its error handler instead, see [`App::set_error_handler`](crate::App::set_error_handler).
+///
+/// # Examples
+///
+/// ```
+/// use http::{HttpError, HttpRequest, JsonValue, Respondable};
+///
+/// fn show(req: HttpRequest) -> Result<String, HttpError> {
+///     let id: u64 = req.path_param("id").ok_or_else(|| HttpError::bad_request("Invalid id"))?;
+///     if id != 1 {
+///         return Err(HttpError::not_found(format!("No user {}", id))
+///             .with_details(JsonValue::Number(id as f64)));
+///     }
+///     Ok("ann".to_string())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpError {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// A description of the error.
+    pub message: String,
+    /// Additional data about the error, e.g. the invalid fields of a form.
+    pub details: Option<JsonValue>,
+}
+
+impl HttpError {
+    /// Creates an error without details.
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into(), details: None }
+    }
+
+    /// Creates a `400 Bad Request` error.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BadRequest, message)
+    }
+
+    /// Creates a `401 Unauthorized` error.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::Unauthorized, message)
+    }
+
+    /// Creates a `403 Forbidden` error.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::Forbidden, message)
+    }
+
+    /// Creates a `404 Not Found` error.
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NotFound, message)
+    }
+
+    /// Creates a `500 Internal Server Error` error.
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::InternalServerError, message)
+    }
+
+    /// Sets the details of the error.
+    pub fn with_details(mut self, details: JsonValue) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Returns `true` if the status is a server error, i.e. `5xx`.
+    pub fn is_server_error(&self) -> bool {
+        self.status.is_server_error()
+    }
+
+    /// Returns the error as a JSON object, e.g. `{"status":404,"error":"No user 7"}`,
+    /// with a `details` field if it has details.
+    pub fn to_json(&self) -> JsonValue {
+        let mut fields = vec![
+            ("status".to_string(), JsonValue::Number(self.status.as_u16() as f64)),
+            ("error".to_string(), JsonValue::String(self.message.clone())),
+        ];
+        if let Some(details) = &self.details {
+            fields.push(("details".to_string(), details.clone()));
+        }
+        JsonValue::Object(fields)
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.status.as_u16(), self.status.reason_phrase(), self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Creates a response with the status of the error and its message as a plain text body.
+impl Respondable for HttpError {
+    fn into_response(self) -> HttpResponse {
+        let mut response = HttpResponse::new(self.status, self.message.clone());
+        response.headers.insert("Content-Type", "text/plain; charset=utf-8");
+        response.error = Some(Box::new(self));
+        response
+    }
+}
+
+impl From<String> for HttpError {
+    fn from(message: String) -> Self {
+        Self::internal(message)
+    }
+}
+
+impl From<&str> for HttpError {
+    fn from(message: &str) -> Self {
+        Self::internal(message)
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        Self::internal(e.to_string())
+    }
+}
+
+impl From<FormError> for HttpError {
+    fn from(e: FormError) -> Self {
+        let status = match e {
+            FormError::UnsupportedMediaType(_) => StatusCode::UnsupportedMediaType,
+            FormError::InvalidBody => StatusCode::BadRequest,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<MultipartError> for HttpError {
+    fn from(e: MultipartError) -> Self {
+        let status = match e {
+            MultipartError::UnsupportedMediaType(_) => StatusCode::UnsupportedMediaType,
+            MultipartError::MissingBoundary | MultipartError::Malformed(_) => StatusCode::BadRequest,
+            MultipartError::PartTooLarge | MultipartError::TooLarge | MultipartError::TooManyParts => StatusCode::ContentTooLarge,
+            MultipartError::Io(_) => StatusCode::InternalServerError,
+        };
+        Self::new(status, e.to_string())
+    }
+}
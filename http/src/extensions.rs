@@ -0,0 +1,67 @@
+use std::{any::{Any, TypeId}, collections::HashMap, sync::Arc};
+
+/// A map of values of different types attached to a request.
+///
+/// Middlewares use it to hand data over to handlers, e.g. the current session.
+/// Every type has at most one value. Values are reference counted,
+/// so cloning a request does not clone them.
+///
+/// # Examples
+///
+/// ```
+/// use http::Extensions;
+///
+/// struct User(String);
+///
+/// let mut extensions = Extensions::new();
+/// extensions.insert(User("ann".to_string()));
+///
+/// assert_eq!(extensions.get::<User>().map(|u| u.0.as_str()), Some("ann"));
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value, replacing the previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns the value of a type.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns `true` if there is a value of the type.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Removes the value of a type, returns `true` if there was one.
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> bool {
+        self.map.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.map.len()).finish()
+    }
+}
@@ -0,0 +1,347 @@
+use std::{fmt::Display, ops::{Deref, DerefMut}, str::FromStr};
+
+use crate::error::HttpError;
+use crate::jsonable::{Jsonable, Parser};
+use crate::server::{HttpRequest, HttpResponse, Respondable, StatusCode};
+use crate::urlencoded::{FormError, Params};
+
+/// A value which can be extracted from a request, e.g. an argument of a handler
+/// wrapped with [`handler`].
+///
+/// A failed extraction returns the response sent instead of calling the handler,
+/// e.g. `400 Bad Request`. The extractors of this crate create it from an [`HttpError`],
+/// so it is rendered by the error handler of the `App`.
+pub trait FromRequest: Sized {
+    /// Extracts the value from the request.
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse>;
+}
+
+/// `None` instead of a rejection, e.g. for an optional header.
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        Ok(T::from_request(req).ok())
+    }
+}
+
+fn reject(status: StatusCode, message: impl Into<String>) -> HttpResponse {
+    HttpError::new(status, message).into_response()
+}
+
+/// Returns `true` if the media type of the `Content-Type` header is JSON,
+/// i.e. `application/json` or a `+json` type like `application/problem+json`.
+fn is_json(content_type: Option<&str>) -> bool {
+    let media_type = content_type.map(|t| t.split(';').next().unwrap_or("").trim().to_ascii_lowercase());
+    media_type.is_some_and(|t| t == "application/json" || (t.starts_with("application/") && t.ends_with("+json")))
+}
+
+macro_rules! wrapper {
+    ($name:ident) => {
+        impl<T> $name<T> {
+            /// Returns the extracted value.
+            pub fn into_inner(self) -> T {
+                self.0
+            }
+        }
+
+        impl<T> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $name<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.0
+            }
+        }
+    };
+}
+
+/// The path parameter of a route with a single parameter, parsed with `FromStr`.
+///
+/// Rejects the request with `400 Bad Request` if the value does not parse.
+/// Routes with several parameters, including the ones of a prefix given to
+/// [`App::nest`](crate::App::nest), read them by name with [`PathParams`]
+/// or [`HttpRequest::path_param`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Path<T>(pub T);
+
+wrapper!(Path);
+
+impl<T> FromRequest for Path<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let mut values = req.path_params.iter();
+        let (name, value) = match (values.next(), values.next()) {
+            (Some(param), None) => param,
+            // A mistake of the application, not of the client
+            _ => return Err(reject(
+                StatusCode::InternalServerError,
+                format!("`Path` needs a route with one parameter, found {}, use `PathParams` to read them by name", req.path_params.len()),
+            )),
+        };
+        value
+            .parse()
+            .map(Path)
+            .map_err(|e| reject(StatusCode::BadRequest, format!("Invalid path parameter `{}`: {}", name, e)))
+    }
+}
+
+/// The path parameters, decoded by name with [`Jsonable::from_form`].
+///
+/// Unlike [`Path`], it works with any number of parameters, e.g. `/orgs/{org}/users/{id}`.
+/// Rejects the request with `400 Bad Request` if they do not decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PathParams<T>(pub T);
+
+wrapper!(PathParams);
+
+impl<T: Jsonable> FromRequest for PathParams<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let mut params = Params::new();
+        for (name, value) in &req.path_params {
+            params.append(name.as_str(), value.as_str());
+        }
+        T::from_form(&params)
+            .map(PathParams)
+            .map_err(|e| reject(StatusCode::BadRequest, format!("Invalid path parameters: {}", e)))
+    }
+}
+
+/// The query parameters, decoded with [`Jsonable::from_form`].
+///
+/// Rejects the request with `400 Bad Request` if they do not decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Query<T>(pub T);
+
+wrapper!(Query);
+
+impl<T: Jsonable> FromRequest for Query<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        T::from_form(&req.query_params)
+            .map(Query)
+            .map_err(|e| reject(StatusCode::BadRequest, format!("Invalid query string: {}", e)))
+    }
+}
+
+/// An `application/x-www-form-urlencoded` body, decoded with [`Jsonable::from_form`].
+///
+/// Rejects the request with `415 Unsupported Media Type` if it has another content type,
+/// `400 Bad Request` if the body is not valid UTF-8, and `422 Unprocessable Content`
+/// if the fields do not decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Form<T>(pub T);
+
+wrapper!(Form);
+
+impl<T: Jsonable> FromRequest for Form<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let params = req.form().map_err(|e| match e {
+            FormError::UnsupportedMediaType(_) => reject(StatusCode::UnsupportedMediaType, e.to_string()),
+            FormError::InvalidBody => reject(StatusCode::BadRequest, e.to_string()),
+        })?;
+        T::from_form(&params)
+            .map(Form)
+            .map_err(|e| reject(StatusCode::UnprocessableContent, format!("Invalid form: {}", e)))
+    }
+}
+
+/// A JSON body, decoded with [`Jsonable::from_json_value`], or a JSON response.
+///
+/// As an argument, it rejects the request with `415 Unsupported Media Type` if its content type
+/// is not JSON, `400 Bad Request` if the body is not valid JSON, and `422 Unprocessable Content`
+/// if the JSON does not decode into the type.
+///
+/// As a response, the value is encoded with [`Jsonable::into_json`] and sent with
+/// `Content-Type: application/json; charset=utf-8`. Pair it with a status code
+/// to replace `200 OK`.
+///
+/// # Examples
+///
+/// ```
+/// use http::{Json, Jsonable, Respondable, StatusCode};
+///
+/// #[derive(Jsonable)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// let response = (StatusCode::Created, Json(User { name: "ann".to_string() })).into_response();
+/// assert_eq!(response.status, StatusCode::Created);
+/// assert_eq!(response.headers.get("Content-Type"), Some("application/json; charset=utf-8"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Json<T>(pub T);
+
+wrapper!(Json);
+
+impl<T: Jsonable> FromRequest for Json<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        if !is_json(req.headers.get("Content-Type")) {
+            return Err(reject(StatusCode::UnsupportedMediaType, "Expected a body of type application/json"));
+        }
+
+        let body = std::str::from_utf8(&req.body).map_err(|_| reject(StatusCode::BadRequest, "The body is not valid UTF-8"))?;
+        let value = Parser::parse_json(body).map_err(|e| reject(StatusCode::BadRequest, format!("Invalid JSON: {}", e)))?;
+        T::from_json_value(&value)
+            .map(Json)
+            .map_err(|e| reject(StatusCode::UnprocessableContent, format!("Invalid JSON body: {}", e)))
+    }
+}
+
+impl<T: Jsonable> Respondable for Json<T> {
+    fn into_response(self) -> HttpResponse {
+        let mut response = HttpResponse::new(StatusCode::Ok, self.0.into_json());
+        response.headers.insert("Content-Type", "application/json; charset=utf-8");
+        response
+    }
+}
+
+/// A header which can be extracted with [`Header`].
+///
+/// # Examples
+///
+/// ```
+/// use http::TypedHeader;
+///
+/// struct ApiKey(String);
+///
+/// impl std::str::FromStr for ApiKey {
+///     type Err = String;
+///
+///     fn from_str(s: &str) -> Result<Self, String> {
+///         Ok(ApiKey(s.to_string()))
+///     }
+/// }
+///
+/// impl TypedHeader for ApiKey {
+///     const NAME: &'static str = "X-Api-Key";
+/// }
+/// ```
+pub trait TypedHeader: FromStr {
+    /// The name of the header.
+    const NAME: &'static str;
+}
+
+/// The first value of a header, parsed with `FromStr`.
+///
+/// Rejects the request with `400 Bad Request` if the header is missing or does not parse.
+/// Wrap it in an `Option` for a header which may be missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Header<T>(pub T);
+
+wrapper!(Header);
+
+impl<T> FromRequest for Header<T>
+where
+    T: TypedHeader,
+    T::Err: Display,
+{
+    fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let value = req
+            .headers
+            .get(T::NAME)
+            .ok_or_else(|| reject(StatusCode::BadRequest, format!("Missing header `{}`", T::NAME)))?;
+        value
+            .trim()
+            .parse()
+            .map(Header)
+            .map_err(|e| reject(StatusCode::BadRequest, format!("Invalid header `{}`: {}", T::NAME, e)))
+    }
+}
+
+/// A function whose arguments are extracted from the request.
+///
+/// Implemented for functions with up to six [`FromRequest`] arguments returning a [`Respondable`].
+/// The raw [`HttpRequest`] can be added as the last argument, it is moved into the function
+/// once the other arguments have been extracted from it.
+pub trait FromRequestHandler<Args>: Send + Sync + 'static {
+    /// Extracts the arguments and calls the function.
+    fn call(&self, req: HttpRequest) -> HttpResponse;
+}
+
+macro_rules! from_request_handler {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> FromRequestHandler<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: Respondable,
+            $($arg: FromRequest,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, req: HttpRequest) -> HttpResponse {
+                $(
+                    let $arg = match $arg::from_request(&req) {
+                        Ok(value) => value,
+                        Err(response) => return response,
+                    };
+                )*
+                self($($arg),*).into_response()
+            }
+        }
+
+        impl<F, R, $($arg,)*> FromRequestHandler<($($arg,)* HttpRequest,)> for F
+        where
+            F: Fn($($arg,)* HttpRequest) -> R + Send + Sync + 'static,
+            R: Respondable,
+            $($arg: FromRequest,)*
+        {
+            #[allow(non_snake_case)]
+            fn call(&self, req: HttpRequest) -> HttpResponse {
+                $(
+                    let $arg = match $arg::from_request(&req) {
+                        Ok(value) => value,
+                        Err(response) => return response,
+                    };
+                )*
+                self($($arg,)* req).into_response()
+            }
+        }
+    };
+}
+
+from_request_handler!();
+from_request_handler!(A);
+from_request_handler!(A, B);
+from_request_handler!(A, B, C);
+from_request_handler!(A, B, C, D);
+from_request_handler!(A, B, C, D, E);
+from_request_handler!(A, B, C, D, E, G);
+
+/// Turns a function with typed arguments into a handler.
+///
+/// Every argument is extracted from the request with [`FromRequest`], in order.
+/// If one fails, its rejection is sent and the function is not called.
+///
+/// # Examples
+///
+/// ```
+/// use http::{handler, App, Jsonable, Json, Path, Query};
+///
+/// #[derive(Jsonable)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// let mut app = App::new();
+///
+/// app.get("/users".to_string(), handler(|Query(filter): Query<User>| {
+///     format!("Users named {}", filter.name)
+/// }));
+///
+/// app.put("/users/{id:u64}".to_string(), handler(|Path(id): Path<u64>, Json(user): Json<User>| {
+///     format!("User {} is now {}", id, user.name)
+/// }));
+/// ```
+pub fn handler<F, Args>(f: F) -> impl Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+where
+    F: FromRequestHandler<Args>,
+    Args: 'static,
+{
+    move |req| f.call(req)
+}
@@ -0,0 +1,159 @@
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::PathBuf};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// The address a server is bound to.
+pub(crate) enum BoundAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// State shared between a running server and its handle.
+#[derive(Default)]
+pub(crate) struct ServerState {
+    /// Set when the server should stop accepting connections.
+    pub(crate) shutdown: AtomicBool,
+    /// Set by the server thread when every connection has been served.
+    finished: Mutex<bool>,
+    finished_changed: Condvar,
+}
+
+impl ServerState {
+    /// Marks the server as finished and wakes up everyone waiting for it.
+    fn finish(&self) {
+        *self.finished.lock().unwrap() = true;
+        self.finished_changed.notify_all();
+    }
+}
+
+/// Marks the server as finished when dropped, even if the server thread panics.
+pub(crate) struct FinishGuard(pub(crate) Arc<ServerState>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// A handle to a server running in the background, returned by [`App::spawn`](crate::App::spawn).
+///
+/// The handle can be shared between threads, e.g. one thread may wait in [`ServerHandle::join`]
+/// while another one calls [`ServerHandle::shutdown`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use http::{App, Respondable};
+///
+/// let mut app = App::new();
+/// app.get("/".to_string(), |_| "Hello world".into_response());
+///
+/// let server = app.spawn(3000).unwrap();
+/// // ...
+/// server.shutdown();
+/// ```
+pub struct ServerHandle {
+    state: Arc<ServerState>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    addr: BoundAddr,
+    shutdown_timeout: Duration,
+}
+
+impl ServerHandle {
+    pub(crate) fn new(
+        state: Arc<ServerState>,
+        thread: JoinHandle<()>,
+        addr: BoundAddr,
+        shutdown_timeout: Duration,
+    ) -> Self {
+        Self {
+            state,
+            thread: Mutex::new(Some(thread)),
+            addr,
+            shutdown_timeout,
+        }
+    }
+
+    /// Returns the address the server is bound to.
+    ///
+    /// This is the real address when the server was started on port `0`.
+    /// Returns `None` for servers on a Unix domain socket.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.addr {
+            BoundAddr::Tcp(addr) => Some(*addr),
+            #[cfg(unix)]
+            BoundAddr::Unix(_) => None,
+        }
+    }
+
+    /// Gracefully stops the server.
+    ///
+    /// The server stops accepting new connections right away, idle connections are closed,
+    /// including the ones which have not sent a request yet, and requests which are already being handled or were already sent,
+    /// e.g. pipelined ones, are allowed to finish within the shutdown timeout (see [`App::set_shutdown_timeout`](crate::App::set_shutdown_timeout)).
+    ///
+    /// Returns `true` if every connection was served before the deadline.
+    pub fn shutdown(&self) -> bool {
+        self.state.shutdown.store(true, Ordering::SeqCst);
+
+        self.wake_accept_loop();
+
+        let deadline = Instant::now() + self.shutdown_timeout;
+        let mut finished = self.state.finished.lock().unwrap();
+        while !*finished {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            finished = self.state.finished_changed.wait_timeout(finished, remaining).unwrap().0;
+        }
+        true
+    }
+
+    /// Connects to the server, because its accept loop is blocked until the next connection arrives.
+    fn wake_accept_loop(&self) {
+        match &self.addr {
+            BoundAddr::Tcp(addr) => {
+                // A connection to an unspecified address is not possible everywhere
+                let ip = match addr.ip() {
+                    IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                    ip => ip,
+                };
+                let _ = TcpStream::connect_timeout(&SocketAddr::new(ip, addr.port()), Duration::from_secs(1));
+            }
+            #[cfg(unix)]
+            BoundAddr::Unix(path) => {
+                let _ = UnixStream::connect(path);
+            }
+        }
+    }
+
+    /// Blocks until the server has stopped.
+    ///
+    /// The server only stops after [`ServerHandle::shutdown`] is called,
+    /// so this is usually called while another thread owns the shutdown.
+    pub fn join(&self) {
+        let mut finished = self.state.finished.lock().unwrap();
+        while !*finished {
+            finished = self.state.finished_changed.wait(finished).unwrap();
+        }
+        drop(finished);
+
+        let thread = self.thread.lock().unwrap().take();
+        if let Some(thread) = thread
+            && thread.join().is_err()
+        {
+            eprintln!("Server thread panicked");
+        }
+    }
+}
@@ -0,0 +1,218 @@
+/// Errors which can happen when adding a header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderError {
+    /// The name is empty or contains characters which are not allowed in a header name.
+    InvalidName(String),
+    /// The value contains control characters, e.g. CR or LF.
+    InvalidValue(String),
+}
+
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderError::InvalidName(n) => write!(f, "Invalid header name: {:?}", n),
+            HeaderError::InvalidValue(v) => write!(f, "Invalid header value: {:?}", v),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+/// A collection of HTTP headers.
+///
+/// Header names are case-insensitive, a name can have several values
+/// (e.g. several `Set-Cookie` headers), and headers are kept in the order they were added.
+/// Names and values are validated, so a header can never break the message framing.
+///
+/// # Examples
+///
+/// ```
+/// use http::HeaderMap;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert("Content-Type", "text/plain");
+/// headers.append("Set-Cookie", "a=1");
+/// headers.append("Set-Cookie", "b=2");
+///
+/// assert_eq!(headers.get("content-type"), Some("text/plain"));
+/// assert_eq!(headers.get_all("set-cookie").collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    /// Creates an empty header map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first value of a header.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns every value of a header, in the order they were added.
+    pub fn get_all<'a, 'n>(&'a self, name: &'n str) -> impl Iterator<Item = &'a str> + use<'a, 'n> {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns `true` if the header is present.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets a header, replacing all of its previous values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or the value is invalid, see [`HeaderMap::try_insert`].
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        if let Err(e) = self.try_insert(name, value) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Adds a value to a header, keeping its previous values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or the value is invalid, see [`HeaderMap::try_append`].
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        if let Err(e) = self.try_append(name, value) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets a header, replacing all of its previous values.
+    ///
+    /// Fails if the name is not a valid token or the value contains control characters.
+    pub fn try_insert(&mut self, name: impl Into<String>, value: impl Into<String>) -> Result<(), HeaderError> {
+        let (name, value) = Self::validate(name.into(), value.into())?;
+        match self.entries.iter().position(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+            Some(pos) => {
+                // The header keeps the position of its first value
+                let mut index = 0;
+                self.entries.retain(|(n, _)| {
+                    let keep = index <= pos || !n.eq_ignore_ascii_case(&name);
+                    index += 1;
+                    keep
+                });
+                self.entries[pos] = (name, value);
+            }
+            None => self.entries.push((name, value)),
+        }
+        Ok(())
+    }
+
+    /// Adds a value to a header, keeping its previous values.
+    ///
+    /// Fails if the name is not a valid token or the value contains control characters.
+    pub fn try_append(&mut self, name: impl Into<String>, value: impl Into<String>) -> Result<(), HeaderError> {
+        let (name, value) = Self::validate(name.into(), value.into())?;
+        self.entries.push((name, value));
+        Ok(())
+    }
+
+    /// Removes every value of a header, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self
+            .entries
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|pos| self.entries.remove(pos).1);
+        self.entries.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        first
+    }
+
+    /// Keeps only the headers for which the predicate returns `true`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&str, &str) -> bool,
+    {
+        self.entries.retain(|(n, v)| f(n, v));
+    }
+
+    /// Returns an iterator over all headers, in the order they were added.
+    ///
+    /// A header with several values is returned once per value.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Returns the number of header values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no headers.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn validate(name: String, value: String) -> Result<(String, String), HeaderError> {
+        if !is_valid_name(&name) {
+            return Err(HeaderError::InvalidName(name));
+        }
+        if !is_valid_value(&value) {
+            return Err(HeaderError::InvalidValue(value));
+        }
+        Ok((name, value))
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+/// Appends every header, like [`HeaderMap::append`].
+///
+/// # Panics
+///
+/// Panics if a name or a value is invalid. Use [`HeaderMap::try_append`] for headers
+/// which come from the client or another untrusted source.
+impl<N: Into<String>, V: Into<String>> Extend<(N, V)> for HeaderMap {
+    fn extend<I: IntoIterator<Item = (N, V)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+/// Collects headers, like [`HeaderMap::append`].
+///
+/// # Panics
+///
+/// Panics if a name or a value is invalid.
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+/// Returns `true` if the name is a valid token (RFC 9110, section 5.1), also used for cookie names.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Returns `true` if the value contains no control characters except tabs (RFC 9110, section 5.5).
+fn is_valid_value(value: &str) -> bool {
+    value.bytes().all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f))
+}
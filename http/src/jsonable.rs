@@ -11,13 +11,33 @@ pub trait Jsonable {
     /// Converts a valid json string into the given type.
     fn from_json(json_string: &str) -> Result<Self, Box<dyn std::error::Error>>
     where Self:Sized;
+
+
+    /// Converts parsed json into the given type.
+    /// 
+    /// Implemented by `#[derive(Jsonable)]`. The default implementation encodes the value
+    /// and decodes it again with [`Jsonable::from_json`].
+    fn from_json_value(value: &JsonValue) -> Result<Self, Box<dyn std::error::Error>>
+    where Self:Sized {
+        Self::from_json(&value.to_string())
+    }
+
+
+    /// Converts decoded form fields into the given type.
+    /// 
+    /// Implemented by `#[derive(Jsonable)]`, every field is read with [`FromFormValue`].
+    fn from_form(params: &crate::urlencoded::Params) -> Result<Self, Box<dyn std::error::Error>>
+    where Self:Sized {
+        let _ = params;
+        Err("Form decoding is not supported by this type".into())
+    }
 }
 
 
 
 
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,Clone,PartialEq)]
 pub enum JsonValue {
     Null,
     Boolean(bool),
@@ -28,6 +48,59 @@ pub enum JsonValue {
 }
 
 
+/// Formats the value as compact JSON, which `Parser::parse_json` reads back.
+impl std::fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Boolean(b) => write!(f, "{}", b),
+            // JSON has no representation of NaN and infinities
+            JsonValue::Number(n) if !n.is_finite() => write!(f, "null"),
+            JsonValue::Number(n) => write!(f, "{}", n),
+            JsonValue::String(s) => write_json_string(f, s),
+            JsonValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            JsonValue::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+
+fn write_json_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+
 
 #[derive(Clone,Debug,PartialEq)]
 pub enum Token {
@@ -109,6 +182,61 @@ impl<T:FromJsonValue> FromJsonValue for Vec<T> {
 
 
 
+/// Conversion of the values of a form field into a field type.
+/// 
+/// `values` holds every value sent for the field, it is empty when the field is missing.
+pub trait FromFormValue: Sized {
+    fn from_form_values(values: &[&str]) -> Result<Self,String>;
+}
+
+
+impl FromFormValue for String {
+    fn from_form_values(values: &[&str]) -> Result<Self,String> {
+        values.first().map(|v| v.to_string()).ok_or_else(|| "Missing value".to_string())
+    }
+}
+
+
+impl FromFormValue for f64 {
+    fn from_form_values(values: &[&str]) -> Result<Self,String> {
+        let value = values.first().ok_or_else(|| "Missing value".to_string())?;
+        value.trim().parse().map_err(|_| format!("Expected number, found {:?}",value))
+    }
+}
+
+
+/// A missing field is `false`, as browsers do not send unchecked checkboxes.
+impl FromFormValue for bool {
+    fn from_form_values(values: &[&str]) -> Result<Self,String> {
+        match values.first() {
+            None => Ok(false),
+            Some(v) if ["on","true","1"].iter().any(|t| v.eq_ignore_ascii_case(t)) => Ok(true),
+            Some(v) if ["off","false","0"].iter().any(|t| v.eq_ignore_ascii_case(t)) => Ok(false),
+            Some(v) => Err(format!("Expected boolean, found {:?}",v)),
+        }
+    }
+}
+
+
+/// A missing or empty field is `None`.
+impl<T:FromFormValue> FromFormValue for Option<T> {
+    fn from_form_values(values: &[&str]) -> Result<Self,String> {
+        match values.first() {
+            None | Some(&"") => Ok(None),
+            _ => Ok(Some(T::from_form_values(values)?)),
+        }
+    }
+}
+
+
+/// Every value of a repeated field, e.g. `tag=a&tag=b`.
+impl<T:FromFormValue> FromFormValue for Vec<T> {
+    fn from_form_values(values: &[&str]) -> Result<Self,String> {
+        values.iter().map(|v| T::from_form_values(&[v])).collect()
+    }
+}
+
+
 
 
 
@@ -129,9 +257,13 @@ fn tokenize(input: &str) -> Result<Vec<Token>,String> {
                 chars.next(); // consume `"`
                 let mut s = String::new();
 
-                while let Some(ch) = chars.next() {
-                    if ch == '"' {break;}
-                    s.push(ch);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.push(unescape(&mut chars)?),
+                        Some(ch) => s.push(ch),
+                        None => return Err("Unterminated string".to_string()),
+                    }
                 }
                 tokens.push(Token::String(s));
             },
@@ -179,6 +311,46 @@ fn tokenize(input: &str) -> Result<Vec<Token>,String> {
 
 
 
+/// Reads the escape sequence following a backslash in a string.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<char,String> {
+    let read_hex = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>| -> Result<u32,String> {
+        let hex: String = chars.by_ref().take(4).collect();
+        u32::from_str_radix(&hex, 16).map_err(|_| format!("Invalid unicode escape: {hex}"))
+    };
+
+    match chars.next() {
+        Some('"') => Ok('"'),
+        Some('\\') => Ok('\\'),
+        Some('/') => Ok('/'),
+        Some('b') => Ok('\x08'),
+        Some('f') => Ok('\x0c'),
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('u') => {
+            let code = read_hex(chars)?;
+            // Characters outside the BMP are written as a surrogate pair
+            let mut ahead = chars.clone();
+            let code = if (0xD800..0xDC00).contains(&code) && ahead.next() == Some('\\') && ahead.next() == Some('u') {
+                *chars = ahead;
+                let low = read_hex(chars)?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err(format!("Invalid surrogate pair: {code:x} {low:x}"));
+                }
+                0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)
+            } else {
+                // A lone surrogate is rejected below, the following characters are left as they are
+                code
+            };
+            char::from_u32(code).ok_or_else(|| format!("Invalid unicode escape: {code:x}"))
+        },
+        Some(c) => Err(format!("Invalid escape: \\{c}")),
+        None => Err("Unterminated string".to_string()),
+    }
+}
+
+
+
 pub struct Parser {
     tokens:Vec<Token>,
     position:usize,
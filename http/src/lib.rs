@@ -7,6 +7,90 @@
 mod server;
 
 
+/// Config module.
+/// 
+/// Contains the server configuration.
+mod config;
+
+
+/// Headers module.
+/// 
+/// Contains the `HeaderMap` type used by requests and responses.
+mod headers;
+
+
+/// Urlencoded module.
+/// 
+/// Contains percent-decoding and the `Params` type used for query strings and form bodies.
+mod urlencoded;
+
+
+/// Multipart module.
+/// 
+/// Contains the streaming parser of `multipart/form-data` bodies.
+mod multipart;
+
+
+/// Cookie module.
+/// 
+/// Contains the request cookie jar and the `Set-Cookie` builder.
+mod cookie;
+
+
+/// Extensions module.
+/// 
+/// Contains the type map used to attach values to a request.
+mod extensions;
+
+
+/// Crypto module.
+/// 
+/// Contains the hashing and signing primitives used by sessions.
+mod crypto;
+
+
+/// Session module.
+/// 
+/// Contains the session middleware and session stores.
+mod session;
+
+
+/// Connection module.
+/// 
+/// Contains buffered reading of requests from a client connection.
+mod connection;
+
+
+/// Handle module.
+/// 
+/// Contains the handle used to stop a server running in the background.
+mod handle;
+
+
+/// Router module.
+/// 
+/// Contains the route tree used to find the handler of a request, and the `Router` type.
+mod router;
+
+
+/// Constraint module.
+/// 
+/// Contains the constraints of path parameters, e.g. `{id:u64}`.
+mod constraint;
+
+
+/// Error module.
+/// 
+/// Contains the `HttpError` type sent to clients when a handler fails.
+mod error;
+
+
+/// Extract module.
+/// 
+/// Contains the `FromRequest` trait and the typed arguments of handlers, e.g. `Path` and `Json`.
+mod extract;
+
+
 /// App module.
 /// 
 /// Contains the basic module builder.
@@ -20,17 +104,29 @@ pub mod jsonable;
 
 
 
-pub use jsonable::{Jsonable,Parser};
+pub use jsonable::{Jsonable,Parser,JsonValue};
 
 
 pub use json::Jsonable;
-pub use app::{App,MiddlewareResult,Middleware,Handler};
-pub use server::{Respondable,HttpRequest,HttpResponse,StatusCode};
+pub use app::{App,MiddlewareResult,Middleware,AroundMiddleware,Next,Handler,ErrorHandler,HandlerPanic,PanicHandler,with_middleware};
+pub use router::Router;
+pub use error::HttpError;
+pub use extract::{handler,FromRequest,FromRequestHandler,Path,PathParams,Query,Form,Json,Header,TypedHeader};
+pub use handle::ServerHandle;
+pub use config::ServerConfig;
+pub use headers::{HeaderMap,HeaderError};
+pub use urlencoded::{Params,FormError,percent_decode};
+pub use extensions::Extensions;
+pub use session::{sessions,Session,SessionConfig,SessionStore,SessionData,MemoryStore};
+pub use cookie::{Cookie,CookieJar,CookieError,SameSite};
+pub use multipart::{Multipart,MultipartConfig,MultipartError,Part,PartData,TempFile};
+pub use server::{Respondable,HttpRequest,HttpResponse,StatusCode,Body,BodyStream};
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
+    use crate::headers::HeaderMap;
     use crate::server::{HttpRequest, Respondable, StatusCode};
 
 
@@ -46,11 +142,15 @@ mod tests {
         let req = HttpRequest {
             method:String::from("GET"),
             uri: String::from("/"),
+            path: String::from("/"),
+            query: String::new(),
             version: String::from("HTTP/1.1"),
-            headers:HashMap::new(),
-            body:String::new(),
+            headers:HeaderMap::new(),
+            body:Vec::new(),
             content_length:0,
-            path_params:HashMap::new()
+            path_params:HashMap::new(),
+            query_params: crate::Params::new(),
+            extensions: crate::Extensions::new(),
         };
 
         let response = app.handle_request(req);
@@ -60,4 +160,1138 @@ mod tests {
 
         
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_app_is_shared_between_workers() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut app = crate::app::App::new();
+        app.set_workers(0);
+        app.get("/".to_string(),|_| "Hello world".into_response());
+
+        assert_send_sync(&app);
+        assert_eq!(app.config.workers,1);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let req = HttpRequest::parse("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert!(req.keep_alive());
+
+        let req = HttpRequest::parse("GET / HTTP/1.1\r\nconnection: Close\r\n\r\n").unwrap();
+        assert!(!req.keep_alive());
+
+        let req = HttpRequest::parse("GET / HTTP/1.0\r\n\r\n").unwrap();
+        assert!(!req.keep_alive());
+
+        let req = HttpRequest::parse("GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        assert!(req.keep_alive());
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        use crate::connection::{Connection, ReadError};
+
+        let input = b"5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nExpires: never\r\n\r\n".to_vec();
+        let mut connection = Connection::new(std::io::Cursor::new(input));
+        let (body, trailers) = connection.read_chunked_body(1024).unwrap();
+
+        assert_eq!(body, b"hello world");
+        assert_eq!(trailers, vec![("Expires".to_string(), "never".to_string())]);
+
+        let mut connection = Connection::new(std::io::Cursor::new(b"zz\r\nhello\r\n0\r\n\r\n".to_vec()));
+        assert!(matches!(connection.read_chunked_body(1024), Err(ReadError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_streamed_response() {
+        use crate::server::HttpResponse;
+
+        let response = HttpResponse::stream(StatusCode::Ok, vec!["hello", "", " world"]);
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        let written = String::from_utf8(bytes).unwrap();
+
+        assert!(written.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!written.contains("Content-Length"));
+        assert!(written.ends_with("\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"));
+
+        // Responses can still be cloned and formatted
+        let response = HttpResponse::new(StatusCode::Ok, "hi");
+        assert_eq!(response.clone().to_string(), "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi");
+        let response = HttpResponse::stream(StatusCode::Ok, vec!["a"]);
+        let copy = response.clone();
+        assert_eq!(response.to_string(), "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+        // Clones share the stream
+        let mut bytes = Vec::new();
+        copy.write_to(&mut bytes).unwrap();
+        assert!(bytes.ends_with(b"1\r\na\r\n0\r\n\r\n"));
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        assert!(bytes.ends_with(b"\r\n\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_binary_body() {
+        let mut app = crate::app::App::new();
+        app.post("/echo".to_string(),|req| req.body.into_response());
+
+        let mut req = HttpRequest::parse("POST /echo HTTP/1.1\r\n\r\n").unwrap();
+        req.body = vec![0xff, 0x00, 0xfe];
+        assert!(req.text().is_err());
+
+        let response = app.handle_request(req);
+        assert_eq!(response.body, &[0xff, 0x00, 0xfe][..]);
+        assert_eq!(response.headers.get("Content-Type").unwrap(), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::time::Duration;
+
+        let mut app = crate::app::App::new();
+        app.get("/slow".to_string(),|_| {
+            std::thread::sleep(Duration::from_millis(200));
+            "done".into_response()
+        });
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+
+        let client = std::thread::spawn(move || {
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(server.shutdown());
+        server.join();
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Connection: close"));
+        assert!(response.ends_with("done"));
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("mhttp-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut app = crate::app::App::new();
+        app.get("/".to_string(),|_| "Hello unix".into_response());
+        let server = app.spawn_unix(&path).unwrap();
+        assert!(server.local_addr().is_none());
+
+        let mut socket = UnixStream::connect(&path).unwrap();
+        socket.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+
+        assert!(response.ends_with("Hello unix"));
+        assert!(server.shutdown());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_server_limits() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::time::Duration;
+
+        use crate::config::ServerConfig;
+
+        let mut app = crate::app::App::new();
+        app.post("/".to_string(),|req| req.body.into_response());
+        app.set_config(ServerConfig {
+            max_header_bytes: 256,
+            max_body_bytes: 16,
+            read_timeout: Some(Duration::from_millis(100)),
+            ..ServerConfig::default()
+        });
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let send = |request: &[u8]| {
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket.write_all(request).unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let long_header = format!("POST / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(300));
+        assert!(send(long_header.as_bytes()).starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+        assert!(send(b"POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n").starts_with("HTTP/1.1 413 Content Too Large"));
+        assert!(send(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n11\r\n").starts_with("HTTP/1.1 413 Content Too Large"));
+        assert!(send(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab").starts_with("HTTP/1.1 408 Request Timeout"));
+        assert!(send(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nConnection: close\r\n\r\nabcde").ends_with("abcde"));
+        assert!(send(b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nabcde").starts_with("HTTP/1.1 400 Bad Request"));
+        let smuggled = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n0\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let response = send(smuggled);
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+        assert!(send(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\n0\r\nBad Name: x\r\n\r\n").starts_with("HTTP/1.1 400 Bad Request"));
+
+        assert!(server.shutdown());
+    }
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(StatusCode::from_u16(204), Some(StatusCode::NoContent));
+        assert_eq!(StatusCode::TooManyRequests.reason_phrase(), "Too Many Requests");
+        assert_eq!(StatusCode::Custom(599).as_u16(), 599);
+        assert_eq!(StatusCode::Custom(404), StatusCode::NotFound);
+        assert_eq!(StatusCode::from_u16(1000), None);
+        assert_eq!(StatusCode::from_u16(42), None);
+        assert_eq!(StatusCode::custom(599), Some(StatusCode::Custom(599)));
+        assert_eq!(StatusCode::custom(99), None);
+        assert!(!StatusCode::Custom(42).is_valid());
+
+        let mut app = crate::app::App::new();
+        app.get("/".to_string(), |_| crate::HttpResponse::new(StatusCode::Custom(42), ""));
+        let response = app.handle_request(HttpRequest::parse("GET / HTTP/1.1\r\n\r\n").unwrap());
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert!(crate::HttpResponse::new(StatusCode::Custom(1000), "").write_to(&mut Vec::new()).is_err());
+
+        let mut written = Vec::new();
+        crate::HttpResponse::new(StatusCode::NotModified, "stale").write_to(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert!(written.ends_with("\r\n\r\n"));
+        assert!(!written.contains("Content-Length") && !written.contains("stale"));
+
+        assert!(StatusCode::Continue.is_informational());
+        assert!(StatusCode::Created.is_success());
+        assert!(StatusCode::NotModified.is_redirection());
+        assert!(StatusCode::UnprocessableContent.is_client_error());
+        assert!(StatusCode::Custom(599).is_server_error());
+        assert!(crate::HttpError::new(StatusCode::Custom(599), "").is_server_error());
+        assert!(!crate::HttpError::new(StatusCode::Custom(600), "").is_server_error());
+
+        assert_eq!(StatusCode::MovedPermanently.to_string(), "301 Moved Permanently");
+        assert_eq!(StatusCode::Custom(599).to_string(), "599");
+    }
+
+    #[test]
+    fn test_header_map() {
+        let req = HttpRequest::parse("GET / HTTP/1.1\r\ncontent-length: 0\r\nAccept: text/html\r\nAccept: application/json\r\n\r\n").unwrap();
+        assert_eq!(req.headers.get("Content-Length"), Some("0"));
+        assert_eq!(req.headers.get_all("accept").collect::<Vec<_>>(), vec!["text/html", "application/json"]);
+        assert!(HttpRequest::parse("GET / HTTP/1.1\r\nBad Name: x\r\n\r\n").is_err());
+        assert_eq!(HttpRequest::parse("POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5, 5\r\n\r\n").unwrap().content_length, 5);
+        assert!(HttpRequest::parse("POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n").is_err());
+        assert!(HttpRequest::parse("POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\n").is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Accept");
+        headers.insert("X-Id", "1");
+        headers.append("vary", "Cookie");
+        headers.insert("VARY", "Origin");
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec![("VARY", "Origin"), ("X-Id", "1")]);
+        assert!(headers.try_insert("X-Injected", "a\r\nSet-Cookie: b").is_err());
+        assert_eq!(headers.remove("x-id"), Some("1".to_string()));
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn test_query_params() {
+        let mut app = crate::app::App::new();
+        app.add_param_handler("/files/{name}".to_string(), "GET".to_string(), |req| {
+            let tags = req.query_params.get_all("tag").join(",");
+            format!("{} {} {}", req.path_params["name"], req.query_params.get("q").unwrap_or(""), tags).into_response()
+        });
+
+        let req = HttpRequest::parse("GET /files/my%20file.txt?q=a+b%26c&tag=x&tag=y HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(req.path, "/files/my file.txt");
+        assert_eq!(req.query, "q=a+b%26c&tag=x&tag=y");
+
+        let response = app.handle_request(req);
+        assert_eq!(response.body, "my file.txt a b&c x,y");
+
+        // An encoded slash does not separate segments
+        let req = HttpRequest::parse("GET /files/a%2Fb HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(req.raw_path(), "/files/a%2Fb");
+        assert_eq!(app.handle_request(req).body, "a/b  ");
+        let req = HttpRequest::parse("GET /files%2Fmy%20file.txt HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(app.handle_request(req).status, StatusCode::NotFound);
+
+        let req = HttpRequest::parse("GET /files/a+b%zz? HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(req.path, "/files/a+b%zz");
+        assert!(req.query_params.is_empty());
+    }
+
+    #[test]
+    fn test_form() {
+        let req = HttpRequest::parse("POST /signup HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded; charset=UTF-8\r\n\r\nname=Ann+Lee&lang=en&lang=fr&age=30").unwrap();
+        let form = req.form().unwrap();
+        assert_eq!(form.get("name"), Some("Ann Lee"));
+        assert_eq!(form.get_all("lang"), vec!["en", "fr"]);
+
+        use crate::jsonable::FromFormValue;
+        assert_eq!(f64::from_form_values(&form.get_all("age")), Ok(30.0));
+        assert_eq!(Vec::<String>::from_form_values(&form.get_all("lang")), Ok(vec!["en".to_string(), "fr".to_string()]));
+        assert_eq!(Option::<String>::from_form_values(&form.get_all("missing")), Ok(None));
+        assert_eq!(bool::from_form_values(&[]), Ok(false));
+
+        let req = HttpRequest::parse("POST /signup HTTP/1.1\r\nContent-Type: application/json\r\n\r\n{}").unwrap();
+        assert_eq!(req.form(), Err(crate::FormError::UnsupportedMediaType(Some("application/json".to_string()))));
+    }
+
+    #[test]
+    fn test_multipart() {
+        use crate::{MultipartConfig, MultipartError};
+
+        let body = "preamble\r\n--b0und\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Hello\r\n\
+            --b0und  \r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a;b.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            \x00\x01\r\n--b0un\x02\r\n\
+            --b0und--\r\n";
+        let mut req = HttpRequest::parse("POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=\"b0und\"\r\n\r\n").unwrap();
+        req.body = body.as_bytes().to_vec();
+
+        let parts = req.multipart().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name.as_deref(), Some("title"));
+        assert_eq!(parts[0].text().unwrap(), "Hello");
+        assert_eq!(parts[1].filename.as_deref(), Some("a;b.bin"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("application/octet-stream"));
+        assert_eq!(parts[1].bytes().unwrap().as_ref(), b"\x00\x01\r\n--b0un\x02");
+
+        // Parts larger than the threshold are written to temporary files which are removed on drop
+        let config = MultipartConfig { spill_threshold: Some(8), ..MultipartConfig::default() };
+        let parts = req.multipart_with(config).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(!parts[0].is_file());
+        assert_eq!(parts[0].text().unwrap(), "Hello");
+        let path = match &parts[1].data {
+            crate::PartData::File(file) => file.path().to_path_buf(),
+            crate::PartData::Memory(_) => panic!("the part was not spilled"),
+        };
+        assert!(path.exists());
+        assert_eq!(parts[1].len(), 11);
+        assert_eq!(parts[1].bytes().unwrap().as_ref(), b"\x00\x01\r\n--b0un\x02");
+        drop(parts);
+        assert!(!path.exists());
+
+        let config = MultipartConfig { max_part_bytes: 4, ..MultipartConfig::default() };
+        assert!(matches!(req.multipart_with(config).unwrap().next(), Some(Err(MultipartError::PartTooLarge))));
+
+        req.headers.insert("Content-Type", "multipart/form-data");
+        assert!(matches!(req.multipart(), Err(MultipartError::MissingBoundary)));
+    }
+
+    #[test]
+    fn test_cookies() {
+        use crate::{Cookie, SameSite};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let req = HttpRequest::parse("GET / HTTP/1.1\r\nCookie: a=1; b=\"two\"\r\nCookie: c=3;;broken\r\n\r\n").unwrap();
+        let jar = req.cookies();
+        assert_eq!(jar.iter().collect::<Vec<_>>(), vec![("a", "1"), ("b", "two"), ("c", "3")]);
+
+        let mut response = crate::HttpResponse::new(StatusCode::Ok, "");
+        response.set_cookie(
+            Cookie::new("id", "x")
+                .domain("example.com")
+                .expires(UNIX_EPOCH + Duration::from_secs(1445412480))
+                .secure(true)
+                .same_site(SameSite::None),
+        );
+        response.set_cookie(Cookie::removal("old").path("/"));
+        assert_eq!(
+            response.headers.get_all("set-cookie").collect::<Vec<_>>(),
+            vec![
+                "id=x; Domain=example.com; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Secure; SameSite=None",
+                "old=; Path=/; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0",
+            ]
+        );
+
+        assert!(Cookie::try_new("id", "a; Path=/").is_err());
+        assert!(Cookie::try_new("bad name", "a").is_err());
+    }
+
+    #[test]
+    fn test_sessions() {
+        use crate::{JsonValue, MemoryStore, SessionConfig};
+
+        let mut app = crate::app::App::new();
+        app.wrap(crate::sessions(SessionConfig::new(*b"0123456789abcdef0123456789abcdef"), MemoryStore::new()));
+        app.get("/login".to_string(), |req| {
+            let session = req.session().unwrap();
+            session.regenerate();
+            session.insert_value("user", JsonValue::String("ann \"a\"".to_string()));
+            "ok".into_response()
+        });
+        app.get("/me".to_string(), |req| {
+            match req.session().unwrap().get_value("user") {
+                Some(JsonValue::String(user)) => user.into_response(),
+                _ => "anonymous".into_response(),
+            }
+        });
+        app.get("/logout".to_string(), |req| {
+            req.session().unwrap().destroy();
+            "bye".into_response()
+        });
+
+        let request = |path: &str, cookie: Option<&str>| {
+            let cookie = cookie.map(|c| format!("Cookie: {}\r\n", c)).unwrap_or_default();
+            app.handle_request(HttpRequest::parse(&format!("GET {} HTTP/1.1\r\n{}\r\n", path, cookie)).unwrap())
+        };
+        let session_cookie = |response: &crate::HttpResponse| {
+            response.headers.get("Set-Cookie").map(|c| c.split(';').next().unwrap().to_string())
+        };
+
+        // Anonymous requests do not create sessions
+        assert_eq!(session_cookie(&request("/me", None)), None);
+
+        let cookie = session_cookie(&request("/login", None)).unwrap();
+        let response = request("/me", Some(&cookie));
+        assert_eq!(response.body, "ann \"a\"");
+        assert_eq!(session_cookie(&response).as_ref(), Some(&cookie));
+
+        // The id is rotated on login, so the old cookie is worthless afterwards
+        let rotated = session_cookie(&request("/login", Some(&cookie))).unwrap();
+        assert_ne!(rotated, cookie);
+        assert_eq!(request("/me", Some(&cookie)).body, "anonymous");
+
+        // A tampered signature is ignored
+        let (id, _) = rotated.split_once('.').unwrap();
+        assert_eq!(request("/me", Some(&format!("{}.forged", id))).body, "anonymous");
+
+        let response = request("/logout", Some(&rotated));
+        assert!(response.headers.get("Set-Cookie").unwrap().contains("Max-Age=0"));
+        assert_eq!(request("/me", Some(&rotated)).body, "anonymous");
+
+        // A session which cannot be saved fails the request, but keeps the headers of the handler
+        struct BrokenStore;
+        impl crate::SessionStore for BrokenStore {
+            fn load(&self, _: &str) -> std::io::Result<Option<crate::SessionData>> {
+                Ok(None)
+            }
+            fn save(&self, _: &str, _: &crate::SessionData, _: std::time::Duration) -> std::io::Result<()> {
+                Err(std::io::Error::other("disk full"))
+            }
+            fn remove(&self, _: &str) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut app = crate::app::App::new();
+        app.wrap(crate::sessions(SessionConfig::new([7u8; 32]), BrokenStore));
+        app.get("/login".to_string(), |req| {
+            req.session().unwrap().insert_value("user", JsonValue::Null);
+            let mut response = "ok".into_response();
+            response.headers.insert("X-Request-Id", "7");
+            response
+        });
+        let response = app.handle_request(HttpRequest::parse("GET /login HTTP/1.1\r\n\r\n").unwrap());
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert_eq!(response.headers.get("X-Request-Id"), Some("7"));
+        assert_eq!(response.headers.get("Set-Cookie"), None);
+        assert!(response.error.is_some());
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(&crate::crypto::sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex(&crate::crypto::hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(crate::crypto::base64url(b"\xfb\xff"), "-_8");
+    }
+
+    #[test]
+    fn test_route_precedence() {
+        let mut app = crate::app::App::new();
+        app.get("/users/{id}/posts".to_string(), |req| format!("posts of {}", req.path_params["id"]).into_response());
+        app.get("/users/{id}".to_string(), |req| format!("user {}", req.path_params["id"]).into_response());
+        app.get("/users/me".to_string(), |_| "me".into_response());
+        app.get("/users/{*rest}".to_string(), |req| format!("rest {}", req.path_params["rest"]).into_response());
+
+        let get = |path: &str| app.handle_request(HttpRequest::parse(&format!("GET {} HTTP/1.1\r\n\r\n", path)).unwrap());
+        assert_eq!(get("/users/me").body, "me");
+        assert_eq!(get("/users/42").body, "user 42");
+        // The static `me` segment has no `posts` child, so the parameter is tried next
+        assert_eq!(get("/users/me/posts").body, "posts of me");
+        assert_eq!(get("/users/42/a/b").body, "rest 42/a/b");
+        assert_eq!(get("/users/").status, StatusCode::NotFound);
+
+        let conflict = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            app.get("/users/{name}".to_string(), |_| "".into_response());
+        }));
+        assert!(conflict.is_err());
+    }
+
+    #[test]
+    fn test_route_constraints() {
+        let mut app = crate::app::App::new();
+        app.get("/users/{id:u64}".to_string(), |req| format!("id {}", req.path_param::<u64>("id").unwrap() + 1).into_response());
+        app.get("/users/{slug:[a-z]+(-[a-z]+)*}".to_string(), |req| format!("slug {}", req.path_params["slug"]).into_response());
+        app.get("/users/{name}".to_string(), |req| format!("name {}", req.path_params["name"]).into_response());
+        app.get("/posts/{year:\\d{4}}/{page?:u32}".to_string(), |req| {
+            format!("{} {:?}", req.path_params["year"], req.path_param::<u32>("page")).into_response()
+        });
+        app.get("/files/{*path}".to_string(), |req| req.path_params["path"].clone().into_response());
+
+        let get = |path: &str| app.handle_request(HttpRequest::parse(&format!("GET {} HTTP/1.1\r\n\r\n", path)).unwrap());
+        assert_eq!(get("/users/41").body, "id 42");
+        assert_eq!(get("/users/ann-lee").body, "slug ann-lee");
+        assert_eq!(get("/users/Ann_Lee").body, "name Ann_Lee");
+        assert_eq!(get("/posts/2024").body, "2024 None");
+        assert_eq!(get("/posts/2024/3").body, "2024 Some(3)");
+        assert_eq!(get("/posts/24/3").status, StatusCode::NotFound);
+        assert_eq!(get("/posts/2024/x").status, StatusCode::NotFound);
+        assert_eq!(get("/files/css/site.css").body, "css/site.css");
+
+        // Long values and nested quantifiers are matched in linear time, without recursion
+        let mut app = crate::app::App::new();
+        app.get("/long/{slug:[a-z]+}".to_string(), |req| req.path_params["slug"].len().to_string().into_response());
+        app.get("/nested/{slug:([a-z]+-?)+}".to_string(), |_| "nested".into_response());
+        let get = |path: &str| app.handle_request(HttpRequest::parse(&format!("GET {} HTTP/1.1\r\n\r\n", path)).unwrap());
+        assert_eq!(get(&format!("/long/{}", "a".repeat(8000))).body, "8000");
+        let start = std::time::Instant::now();
+        assert_eq!(get(&format!("/nested/{}!", "a".repeat(26))).status, StatusCode::NotFound);
+        assert_eq!(get(&format!("/nested/{}-b", "a".repeat(26))).body, "nested");
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+
+        let regex = |source: &str| crate::constraint::Regex::new(source).unwrap();
+        assert!(regex("(a*)*b").is_match("aaab"));
+        assert!(regex("ab|cd|").is_match(""));
+        assert!(regex("x(ab|cd){2,3}").is_match("xabcdab"));
+        assert!(!regex("x(ab|cd){2,3}").is_match("xab"));
+        assert!(!regex("x(ab|cd){2,3}").is_match("xabcdabcd"));
+        assert!(crate::constraint::Regex::new("(a{100}){1000}").is_err());
+
+        for invalid in ["/a/{x:[a-z}", "/a/{*rest}/b", "/a/{x?}/b", "/a/{page:u64?}", "/a/{x:[a-z]-?}"] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                crate::app::App::new().get(invalid.to_string(), |_| "".into_response());
+            }));
+            assert!(result.is_err(), "{}", invalid);
+        }
+
+        // Two type constraints or two regexes at the same position are ambiguous
+        for (first, second) in [("/a/{x:u64}", "/a/{y:i64}"), ("/a/{x:[a-z]+}", "/a/{y:[a-c]+}"), ("/a/{x:u64}", "/a/{x:i64}")] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let mut app = crate::app::App::new();
+                app.get(first.to_string(), |_| "".into_response());
+                app.get(second.to_string(), |_| "".into_response());
+            }));
+            assert!(result.is_err(), "{} {}", first, second);
+        }
+
+        // The type constraint wins whatever the registration order
+        let mut app = crate::app::App::new();
+        app.get("/a/{slug:\\w+}".to_string(), |_| "regex".into_response());
+        app.get("/a/{id:u64}".to_string(), |_| "type".into_response());
+        let get = |path: &str| app.handle_request(HttpRequest::parse(&format!("GET {} HTTP/1.1\r\n\r\n", path)).unwrap());
+        assert_eq!(get("/a/42").body, "type");
+        assert_eq!(get("/a/x42").body, "regex");
+    }
+
+    #[test]
+    fn test_method_routing() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+
+        let mut app = crate::app::App::new();
+        app.get("/items/{id}".to_string(), |_| "item".into_response());
+        app.patch("/items/{id}".to_string(), |_| "patched".into_response());
+        app.post("/items/new".to_string(), |_| "created".into_response());
+        app.any("/echo".to_string(), |req| req.method.into_response());
+        app.delete("/echo".to_string(), |_| "deleted".into_response());
+
+        let request = |method: &str, path: &str| app.handle_request(HttpRequest::parse(&format!("{} {} HTTP/1.1\r\n\r\n", method, path)).unwrap());
+        assert_eq!(request("PATCH", "/items/1").body, "patched");
+        // `/items/new` has no GET handler, so the parameter route serves it
+        assert_eq!(request("GET", "/items/new").body, "item");
+        assert_eq!(request("PUT", "/echo").body, "PUT");
+        assert_eq!(request("DELETE", "/echo").body, "deleted");
+
+        let response = request("DELETE", "/items/1");
+        assert_eq!(response.status, StatusCode::MethodNotAllowed);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, PATCH, OPTIONS"));
+
+        let response = request("OPTIONS", "/items/1");
+        assert_eq!(response.status, StatusCode::NoContent);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, PATCH, OPTIONS"));
+        assert_eq!(request("OPTIONS", "/echo").body, "OPTIONS");
+        assert_eq!(request("GET", "/missing").status, StatusCode::NotFound);
+
+        // HEAD is answered by the GET handler, without the body
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let mut socket = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        socket.write_all(b"HEAD /items/1 HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Length: 4\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_nest() {
+        let request = |app: &crate::app::App, method: &str, path: &str| app.handle_request(HttpRequest::parse(&format!("{} {} HTTP/1.1\r\n\r\n", method, path)).unwrap());
+
+        let mut users = crate::Router::new();
+        users.get("/".to_string(), |req| format!("users of {}", req.path_params["org"]).into_response());
+        users.get("/{id:u64}".to_string(), |req| format!("user {} of {}", req.path_params["id"], req.path_params["org"]).into_response());
+        users.add_param_handler("/{id:u64}".to_string(), "DELETE".to_string(), |_| "deleted".into_response());
+
+        let mut api = crate::Router::new();
+        api.get("/status".to_string(), |_| "ok".into_response());
+        api.nest("/orgs/{org}/users/", users);
+        assert_eq!(api.len(), 4);
+
+        let mut app = crate::app::App::new();
+        app.get("/status".to_string(), |_| "root".into_response());
+        app.nest("/api/v1", api);
+
+        assert_eq!(request(&app, "GET", "/status").body, "root");
+        assert_eq!(request(&app, "GET", "/api/v1/status").body, "ok");
+        assert_eq!(request(&app, "GET", "/api/v1/orgs/acme/users").body, "users of acme");
+        assert_eq!(request(&app, "GET", "/api/v1/orgs/acme/users/7").body, "user 7 of acme");
+        assert_eq!(request(&app, "DELETE", "/api/v1/orgs/acme/users/7").body, "deleted");
+        assert_eq!(request(&app, "GET", "/api/v1/orgs/acme/users/ann").status, StatusCode::NotFound);
+        assert_eq!(request(&app, "GET", "/api/v2/status").status, StatusCode::NotFound);
+
+        // Invalid patterns are reported when they are registered
+        let result = std::panic::catch_unwind(|| crate::Router::new().get("/{*rest}/x".to_string(), |_| "".into_response()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_scoped_middleware() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::app::MiddlewareResult;
+        use crate::server::HttpResponse;
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logger = |name: &'static str| {
+            let log = Arc::clone(&log);
+            move |req: HttpRequest| {
+                log.lock().unwrap().push(format!("{} {:?}", name, req.path_params.get("id")));
+                MiddlewareResult::Continue(req)
+            }
+        };
+
+        let mut app = crate::app::App::new();
+        app.use_middleware(logger("global"));
+        app.group("/admin", |admin| {
+            admin.use_middleware(logger("admin"));
+            admin.wrap(|req: HttpRequest, next: crate::Next| {
+                if req.headers.get("Authorization").is_none() {
+                    return HttpResponse::new(StatusCode::Unauthorized, "Unauthorized");
+                }
+                let mut response = next.run(req);
+                response.headers.insert("X-Admin", "1");
+                response
+            });
+
+            let mut users = crate::Router::new();
+            users.use_middleware(logger("users"));
+            users.get("/{id}".to_string(), |_| "user".into_response());
+            admin.nest("/users", users);
+        });
+        app.post("/login".to_string(), crate::with_middleware(vec![Box::new(logger("login"))], |_| "welcome".into_response()));
+        app.get("/".to_string(), |_| "home".into_response());
+
+        let request = |raw: &str| app.handle_request(HttpRequest::parse(raw).unwrap());
+
+        let response = request("GET /admin/users/7 HTTP/1.1\r\nAuthorization: yes\r\n\r\n");
+        assert_eq!(response.body, "user");
+        assert_eq!(response.headers.get("X-Admin"), Some("1"));
+        assert_eq!(*log.lock().unwrap(), ["global None", "admin Some(\"7\")", "users Some(\"7\")"]);
+
+        log.lock().unwrap().clear();
+        let response = request("GET /admin/users/7 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Unauthorized);
+        assert_eq!(*log.lock().unwrap(), ["global None", "admin Some(\"7\")"]);
+
+        log.lock().unwrap().clear();
+        assert_eq!(request("GET / HTTP/1.1\r\n\r\n").body, "home");
+        assert_eq!(request("POST /login HTTP/1.1\r\n\r\n").body, "welcome");
+        assert_eq!(*log.lock().unwrap(), ["global None", "global None", "login None"]);
+    }
+
+    #[test]
+    fn test_around_middleware() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::app::{MiddlewareResult, Next};
+        use crate::server::HttpResponse;
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let around = |name: &'static str| {
+            let log = Arc::clone(&log);
+            move |req: HttpRequest, next: Next| {
+                log.lock().unwrap().push(format!("before {}", name));
+                let mut response = next.run(req);
+                log.lock().unwrap().push(format!("after {}", name));
+                response.headers.insert("X-Layer", name);
+                response
+            }
+        };
+
+        let mut app = crate::app::App::new();
+        app.wrap(around("outer"));
+        let seen = Arc::clone(&log);
+        app.use_middleware(move |req| {
+            seen.lock().unwrap().push("legacy".to_string());
+            MiddlewareResult::Continue(req)
+        });
+        app.wrap(around("inner"));
+        // Rewrites errors of the handlers
+        app.wrap(|req: HttpRequest, next: Next| {
+            let response = next.run(req);
+            if response.status == StatusCode::NotFound {
+                return HttpResponse::new(StatusCode::NotFound, "Nothing here");
+            }
+            response
+        });
+        // Middlewares can still be added to the public list directly
+        let seen = Arc::clone(&log);
+        app.middlewares.push(Box::new(move |req| {
+            seen.lock().unwrap().push("late".to_string());
+            MiddlewareResult::Continue(req)
+        }));
+        app.group("/private", |private| {
+            private.wrap(|_: HttpRequest, _: Next| HttpResponse::new(StatusCode::Forbidden, "Forbidden"));
+            private.get("/".to_string(), |_| "secret".into_response());
+        });
+        app.get("/".to_string(), |_| "home".into_response());
+
+        let request = |path: &str| app.handle_request(HttpRequest::parse(&format!("GET {} HTTP/1.1\r\n\r\n", path)).unwrap());
+
+        let response = request("/");
+        assert_eq!(response.body, "home");
+        // The outer middleware changes the response last
+        assert_eq!(response.headers.get("X-Layer"), Some("outer"));
+        assert_eq!(*log.lock().unwrap(), ["before outer", "legacy", "before inner", "late", "after inner", "after outer"]);
+
+        let response = request("/missing");
+        assert_eq!(response.status, StatusCode::NotFound);
+        assert_eq!(response.body, "Nothing here");
+
+        let response = request("/private");
+        assert_eq!(response.status, StatusCode::Forbidden);
+        assert_eq!(response.headers.get("X-Layer"), Some("outer"));
+    }
+
+    #[test]
+    fn test_extractors() {
+        use crate::{handler, Header, Json, Path, PathParams, Query, TypedHeader};
+        use crate::jsonable::{FromFormValue, Jsonable, JsonValue, Parser};
+
+        struct Item {
+            name: String,
+        }
+
+        thread_local! {
+            static PARSES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        }
+
+        impl Jsonable for Item {
+            fn into_json(&self) -> String {
+                JsonValue::Object(vec![("name".to_string(), JsonValue::String(self.name.clone()))]).to_string()
+            }
+
+            fn from_json(json_string: &str) -> Result<Self, Box<dyn std::error::Error>> {
+                PARSES.with(|parses| parses.set(parses.get() + 1));
+                Self::from_json_value(&Parser::parse_json(json_string)?)
+            }
+
+            fn from_json_value(value: &JsonValue) -> Result<Self, Box<dyn std::error::Error>> {
+                match value {
+                    JsonValue::Object(fields) => match fields.iter().find(|(k, _)| k == "name") {
+                        Some((_, JsonValue::String(name))) => Ok(Item { name: name.clone() }),
+                        _ => Err("Missing field `name`".into()),
+                    },
+                    _ => Err("Expected an object".into()),
+                }
+            }
+
+            fn from_form(params: &crate::Params) -> Result<Self, Box<dyn std::error::Error>> {
+                Ok(Item { name: String::from_form_values(&params.get_all("name"))? })
+            }
+        }
+
+        struct Token(u32);
+
+        impl std::str::FromStr for Token {
+            type Err = std::num::ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map(Token)
+            }
+        }
+
+        impl TypedHeader for Token {
+            const NAME: &'static str = "X-Token";
+        }
+
+        let mut app = crate::app::App::new();
+        app.get("/items".to_string(), handler(|Query(item): Query<Item>, token: Option<Header<Token>>| {
+            format!("{} {:?}", item.name, token.map(|t| t.0.0))
+        }));
+        app.put("/items/{id}".to_string(), handler(|Path(id): Path<u32>, Json(item): Json<Item>, req: HttpRequest| {
+            format!("{} {} {}", id, item.name, req.method)
+        }));
+        app.get("/token".to_string(), handler(|Header(token): Header<Token>| token.0.to_string()));
+        app.get("/ping".to_string(), handler(|| "pong"));
+
+        let request = |raw: &str| app.handle_request(HttpRequest::parse(raw).unwrap());
+
+        assert_eq!(request("GET /ping HTTP/1.1\r\n\r\n").body, "pong");
+        assert_eq!(request("GET /items?name=pen HTTP/1.1\r\n\r\n").body, "pen None");
+        assert_eq!(request("GET /items?name=pen HTTP/1.1\r\nX-Token: 7\r\n\r\n").body, "pen Some(7)");
+        assert_eq!(request("GET /items HTTP/1.1\r\n\r\n").status, StatusCode::BadRequest);
+        assert_eq!(request("GET /token HTTP/1.1\r\nX-Token: 42\r\n\r\n").body, "42");
+        assert_eq!(request("GET /token HTTP/1.1\r\n\r\n").status, StatusCode::BadRequest);
+        assert_eq!(request("GET /token HTTP/1.1\r\nX-Token: abc\r\n\r\n").status, StatusCode::BadRequest);
+
+        let put = |id: &str, content_type: &str, body: &str| {
+            request(&format!("PUT /items/{} HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}", id, content_type, body.len(), body))
+        };
+        assert_eq!(put("3", "application/json; charset=utf-8", r#"{"name":"pen"}"#).body, "3 pen PUT");
+        // The body is parsed once by the extractor, not again by the type
+        assert_eq!(PARSES.with(|parses| parses.get()), 0);
+        assert_eq!(put("x", "application/json", r#"{"name":"pen"}"#).status, StatusCode::BadRequest);
+        assert_eq!(put("3", "text/plain", r#"{"name":"pen"}"#).status, StatusCode::UnsupportedMediaType);
+        assert_eq!(put("3", "application/json", r#"{"name":"#).status, StatusCode::BadRequest);
+        assert_eq!(put("3", "application/json", r#"{"title":"pen"}"#).status, StatusCode::UnprocessableContent);
+
+        // A nested route has the parameters of the prefix as well, they are read by name
+        let mut shop = crate::Router::new();
+        shop.get("/items/{name}".to_string(), handler(|PathParams(item): PathParams<Item>, req: HttpRequest| {
+            format!("{} of {}", item.name, req.path_params["shop"])
+        }));
+        shop.get("/ids/{id}".to_string(), handler(|Path(id): Path<u32>| id.to_string()));
+        let mut app = crate::app::App::new();
+        app.nest("/shops/{shop}", shop);
+        let request = |raw: &str| app.handle_request(HttpRequest::parse(raw).unwrap());
+        assert_eq!(request("GET /shops/acme/items/pen HTTP/1.1\r\n\r\n").body, "pen of acme");
+        let response = request("GET /shops/acme/ids/3 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert!(response.error.unwrap().message.contains("`PathParams`"));
+    }
+
+    #[test]
+    fn test_json_response() {
+        use crate::{handler, Json, Path};
+        use crate::jsonable::{Jsonable, JsonValue};
+
+        struct User {
+            id: u32,
+            name: String,
+        }
+
+        impl Jsonable for User {
+            fn into_json(&self) -> String {
+                JsonValue::Object(vec![
+                    ("id".to_string(), JsonValue::Number(self.id as f64)),
+                    ("name".to_string(), JsonValue::String(self.name.clone())),
+                ]).to_string()
+            }
+
+            fn from_json(_: &str) -> Result<Self, Box<dyn std::error::Error>> {
+                Err("Not needed".into())
+            }
+        }
+
+        let mut app = crate::app::App::new();
+        app.get("/users/{id}".to_string(), handler(|Path(id): Path<u32>| Json(User { id, name: "ann".to_string() })));
+        app.post("/users".to_string(), handler(|| (StatusCode::Created, Json(User { id: 2, name: "bob \"b\"".to_string() }))));
+        app.delete("/users/{id}".to_string(), |_| (StatusCode::Accepted, "deleting").into_response());
+
+        let request = |raw: &str| app.handle_request(HttpRequest::parse(raw).unwrap());
+
+        let response = request("GET /users/1 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Ok);
+        assert_eq!(response.headers.get("Content-Type"), Some("application/json; charset=utf-8"));
+        assert_eq!(response.body, r#"{"id":1,"name":"ann"}"#);
+
+        let response = request("POST /users HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Created);
+        assert_eq!(response.status_message, "Created");
+        assert_eq!(response.headers.get("Content-Length"), Some("27"));
+        assert_eq!(response.body, r#"{"id":2,"name":"bob \"b\""}"#);
+
+        let response = request("DELETE /users/1 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Accepted);
+        assert_eq!(response.body, "deleting");
+
+        // Characters outside the BMP are escaped as surrogate pairs
+        let parse = crate::jsonable::Parser::parse_json;
+        assert_eq!(parse(r#""\ud83d\ude00""#), Ok(JsonValue::String("\u{1F600}".to_string())));
+        assert!(parse(r#""\ud83d\u0041""#).is_err());
+        assert!(parse(r#""\ud83dx""#).is_err());
+        assert!(parse(r#""\ude00""#).is_err());
+    }
+
+    #[test]
+    fn test_error_handling() {
+        use crate::{handler, HttpError, Path};
+        use crate::jsonable::JsonValue;
+        use crate::server::HttpResponse;
+
+        let mut app = crate::app::App::new();
+        app.get("/fail".to_string(), |_| Err::<String, _>("database is down".to_string()).into_response());
+        fn form_name(req: HttpRequest) -> Result<String, HttpError> {
+            let form = req.form()?;
+            Ok(form.get("name").unwrap_or("").to_string())
+        }
+        app.post("/form".to_string(), |req| form_name(req).into_response());
+        app.get("/users/{id}".to_string(), handler(|Path(id): Path<u32>| {
+            Err::<String, _>(HttpError::not_found(format!("No user {}", id)).with_details(JsonValue::Number(id as f64)))
+        }));
+
+        let request = |app: &crate::app::App, raw: &str| app.handle_request(HttpRequest::parse(raw).unwrap());
+
+        // Without an error handler, errors are sent as plain text
+        let response = request(&app, "GET /fail HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert_eq!(response.body, "database is down");
+        assert_eq!(response.error.as_ref().map(|e| e.message.as_str()), Some("database is down"));
+        let response = request(&app, "POST /form HTTP/1.1\r\nContent-Type: text/plain\r\n\r\n");
+        assert_eq!(response.status, StatusCode::UnsupportedMediaType);
+        assert_eq!(request(&app, "GET /users/7 HTTP/1.1\r\n\r\n").body, "No user 7");
+        assert!(request(&app, "GET /users/7 HTTP/1.1\r\n\r\n").error.is_some());
+
+        app.set_error_handler(|e: &HttpError| {
+            let mut response = HttpResponse::new(e.status, e.to_json().to_string());
+            response.headers.insert("Content-Type", "application/json");
+            response
+        });
+        app.set_expose_internal_errors(false);
+        app.wrap(|req, next: crate::Next| {
+            let mut response = next.run(req);
+            response.headers.append("Set-Cookie", "a=1");
+            response.headers.append("Set-Cookie", "b=2");
+            response
+        });
+
+        let response = request(&app, "GET /fail HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert_eq!(response.body, r#"{"status":500,"error":"Internal Server Error"}"#);
+        assert_eq!(response.headers.get("Content-Type"), Some("application/json"));
+        assert_eq!(response.headers.get_all("Set-Cookie").collect::<Vec<_>>(), ["a=1", "b=2"]);
+
+        let response = request(&app, "GET /users/7 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.body, r#"{"status":404,"error":"No user 7","details":7}"#);
+        let response = request(&app, "GET /users/x HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::BadRequest);
+        assert_eq!(response.body, r#"{"status":400,"error":"Invalid path parameter `id`: invalid digit found in string"}"#);
+
+        let response = request(&app, "DELETE /fail HTTP/1.1\r\n\r\n");
+        assert_eq!(response.body, r#"{"status":405,"error":"Method Not Allowed"}"#);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+        assert_eq!(request(&app, "GET /missing HTTP/1.1\r\n\r\n").body, r#"{"status":404,"error":"Not Found"}"#);
+
+        // Errors are rendered with the status the response has in the end
+        app.get("/busy".to_string(), |_| (StatusCode::ServiceUnavailable, Err::<String, _>(HttpError::internal("maintenance"))).into_response());
+        app.get("/old".to_string(), |_| Err::<String, _>(HttpError::not_found("Moved away")).into_response());
+        app.wrap(|req: HttpRequest, next: crate::Next| {
+            let old = req.path == "/old";
+            let mut response = next.run(req);
+            if old {
+                response.status = StatusCode::Gone;
+            }
+            response
+        });
+        let response = request(&app, "GET /busy HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::ServiceUnavailable);
+        assert_eq!(response.body, r#"{"status":503,"error":"Service Unavailable"}"#);
+        let response = request(&app, "GET /old HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Gone);
+        assert_eq!(response.body, r#"{"status":410,"error":"Moved away"}"#);
+
+        // A response can still be built as a struct
+        let response = HttpResponse {
+            version: "HTTP/1.1".to_string(),
+            status: StatusCode::Accepted,
+            status_message: "Accepted".to_string(),
+            headers: crate::HeaderMap::new(),
+            body: crate::Body::Full(b"queued".to_vec()),
+            error: None,
+        };
+        assert_eq!(response.to_string(), "HTTP/1.1 202 Accepted\r\nContent-Length: 6\r\n\r\nqueued");
+    }
+
+    #[test]
+    fn test_panic_isolation() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::sync::{Arc, Mutex};
+
+        use crate::server::HttpResponse;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&reports);
+
+        let mut app = crate::app::App::new();
+        // A single worker shows that it survives the panics
+        app.set_workers(1);
+        app.set_panic_handler(move |p: &crate::HandlerPanic| {
+            seen.lock().unwrap().push(format!("{} {} {}", p.method, p.path, p.message));
+        });
+        app.get("/boom/{id}".to_string(), |req| panic!("bad id {}", req.path_params["id"]));
+        app.get("/stream".to_string(), |_| {
+            HttpResponse::stream(StatusCode::Ok, (0..3).map(|i| if i < 2 { "chunk" } else { panic!("stream failed") }))
+        });
+        app.get("/ok".to_string(), |_| "ok".into_response());
+
+        let response = app.handle_request(HttpRequest::parse("GET /boom/7 HTTP/1.1\r\n\r\n").unwrap());
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert_eq!(*reports.lock().unwrap(), ["GET /boom/7 bad id 7"]);
+
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let get = |path: &str| {
+            let mut socket = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+            socket.write_all(format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path).as_bytes()).unwrap();
+            let mut response = Vec::new();
+            // The connection of a failed stream is closed, possibly while the client still reads
+            let _ = socket.read_to_end(&mut response);
+            String::from_utf8_lossy(&response).into_owned()
+        };
+
+        assert!(get("/boom/8").starts_with("HTTP/1.1 500 Internal Server Error"));
+        assert!(get("/stream").starts_with("HTTP/1.1 200 OK"));
+        assert!(get("/ok").ends_with("\r\n\r\nok"));
+        server.shutdown();
+
+        assert_eq!(reports.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_idle_connections() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::time::{Duration, Instant};
+
+        let mut app = crate::app::App::new();
+        app.get("/".to_string(), |_| "served".into_response());
+        app.set_workers(2);
+        app.set_keep_alive_timeout(Duration::from_millis(300));
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Connections which never send a request occupy both workers
+        let mut idle: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("served"));
+        // The read timeout is 30 seconds, the idle connections are released after the keep-alive timeout
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        for socket in &mut idle {
+            let mut rest = Vec::new();
+            socket.read_to_end(&mut rest).unwrap();
+            assert!(rest.is_empty());
+        }
+        assert!(server.shutdown());
+
+        // By default, the idle connections of a few browsers do not hold every worker
+        let mut app = crate::app::App::new();
+        app.get("/".to_string(), |_| "served".into_response());
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let _idle: Vec<TcpStream> = (0..12).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("served"));
+        // Well below the keep-alive timeout of 5 seconds
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(server.shutdown());
+    }
+
+    #[test]
+    fn test_shutdown_with_idle_client() {
+        use std::io::Read;
+        use std::net::TcpStream;
+        use std::time::{Duration, Instant};
+
+        let mut app = crate::app::App::new();
+        app.get("/".to_string(), |_| "served".into_response());
+        app.set_shutdown_timeout(Duration::from_secs(5));
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+
+        // Connected, but never sends a request
+        let mut idle = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        assert!(server.shutdown());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let mut rest = Vec::new();
+        idle.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_shutdown_serves_sent_requests() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::time::Duration;
+
+        let mut app = crate::app::App::new();
+        app.set_workers(1);
+        app.get("/slow".to_string(), |_| {
+            std::thread::sleep(Duration::from_millis(300));
+            "slow".into_response()
+        });
+        app.get("/".to_string(), |_| "served".into_response());
+        let server = app.spawn_on("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // The second request is pipelined behind the slow one
+        let mut pipelined = TcpStream::connect(addr).unwrap();
+        pipelined.write_all(b"GET /slow HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        // Accepted, but waiting for the only worker
+        let mut queued = TcpStream::connect(addr).unwrap();
+        queued.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(server.shutdown());
+
+        let mut response = String::new();
+        pipelined.read_to_string(&mut response).unwrap();
+        assert!(response.contains("slow"));
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(response.ends_with("served"));
+
+        let mut response = String::new();
+        queued.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("served"));
+    }
+}
@@ -0,0 +1,575 @@
+use std::{borrow::Cow, env, fs::{self, File, OpenOptions}, io::{self, Read, Write}, path::{Path, PathBuf}, process, sync::atomic::{AtomicUsize, Ordering}, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::headers::HeaderMap;
+
+/// The maximum size of the headers of a single part.
+const MAX_PART_HEADER_BYTES: usize = 8 * 1024;
+
+/// The number of bytes read from the body at once.
+const READ_CHUNK_BYTES: usize = 8 * 1024;
+
+/// Limits and storage settings of the multipart parser.
+///
+/// Parts are kept in memory unless `spill_threshold` is set. The body of a request is read before
+/// its handler is called, so the size of an upload is also limited by
+/// [`ServerConfig::max_body_bytes`](crate::ServerConfig::max_body_bytes).
+///
+/// # Examples
+///
+/// ```
+/// use http::MultipartConfig;
+///
+/// // Keep parts up to 64 KiB in memory and write larger ones to temporary files
+/// let config = MultipartConfig {
+///     max_part_bytes: 50 * 1024 * 1024,
+///     max_total_bytes: 100 * 1024 * 1024,
+///     spill_threshold: Some(64 * 1024),
+///     ..MultipartConfig::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultipartConfig {
+    /// The maximum size of the content of a single part in bytes.
+    pub max_part_bytes: usize,
+    /// The maximum size of the content of all parts in bytes.
+    pub max_total_bytes: usize,
+    /// The maximum number of parts.
+    pub max_parts: usize,
+    /// Parts larger than this are written to a temporary file, `None` keeps every part in memory.
+    pub spill_threshold: Option<usize>,
+    /// The directory of the temporary files.
+    pub temp_dir: PathBuf,
+}
+
+impl Default for MultipartConfig {
+    /// Creates the default configuration:
+    ///
+    /// * 2 MiB per part and in total, at most 100 parts,
+    /// * every part is kept in memory,
+    /// * temporary files go to [`std::env::temp_dir`].
+    fn default() -> Self {
+        Self {
+            max_part_bytes: 2 * 1024 * 1024,
+            max_total_bytes: 2 * 1024 * 1024,
+            max_parts: 100,
+            spill_threshold: None,
+            temp_dir: env::temp_dir(),
+        }
+    }
+}
+
+/// Errors which can happen when reading a multipart body.
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The request has no `multipart/form-data` content type, the actual one is attached.
+    UnsupportedMediaType(Option<String>),
+    /// The content type has no `boundary` parameter.
+    MissingBoundary,
+    /// The body does not follow the multipart format.
+    Malformed(String),
+    /// A part is larger than [`MultipartConfig::max_part_bytes`].
+    PartTooLarge,
+    /// The parts are larger than [`MultipartConfig::max_total_bytes`].
+    TooLarge,
+    /// There are more parts than [`MultipartConfig::max_parts`].
+    TooManyParts,
+    /// Reading the body or writing a temporary file failed.
+    Io(io::Error),
+}
+
+impl std::fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::UnsupportedMediaType(Some(t)) => write!(f, "Unsupported media type: {}", t),
+            MultipartError::UnsupportedMediaType(None) => write!(f, "Missing Content-Type"),
+            MultipartError::MissingBoundary => write!(f, "Missing multipart boundary"),
+            MultipartError::Malformed(m) => write!(f, "Malformed multipart body: {}", m),
+            MultipartError::PartTooLarge => write!(f, "Multipart part too large"),
+            MultipartError::TooLarge => write!(f, "Multipart body too large"),
+            MultipartError::TooManyParts => write!(f, "Too many multipart parts"),
+            MultipartError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+impl From<io::Error> for MultipartError {
+    fn from(e: io::Error) -> Self {
+        MultipartError::Io(e)
+    }
+}
+
+/// A single part of a `multipart/form-data` body.
+#[derive(Debug)]
+pub struct Part {
+    /// The headers of the part.
+    pub headers: HeaderMap,
+    /// The `name` of the `Content-Disposition` header.
+    pub name: Option<String>,
+    /// The `filename` of the `Content-Disposition` header, set for file uploads.
+    pub filename: Option<String>,
+    /// The `Content-Type` of the part.
+    pub content_type: Option<String>,
+    /// The content of the part.
+    pub data: PartData,
+}
+
+impl Part {
+    /// Returns the size of the content in bytes.
+    pub fn len(&self) -> u64 {
+        match &self.data {
+            PartData::Memory(bytes) => bytes.len() as u64,
+            PartData::File(file) => file.len(),
+        }
+    }
+
+    /// Returns `true` if the content is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the content was written to a temporary file.
+    pub fn is_file(&self) -> bool {
+        matches!(self.data, PartData::File(_))
+    }
+
+    /// Returns the content, reading it from the temporary file if needed.
+    pub fn bytes(&self) -> io::Result<Cow<'_, [u8]>> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(Cow::Borrowed(bytes)),
+            PartData::File(file) => fs::read(file.path()).map(Cow::Owned),
+        }
+    }
+
+    /// Returns the content as text, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn text(&self) -> io::Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes()?).into_owned())
+    }
+}
+
+/// Where the content of a part is stored.
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+/// A temporary file holding the content of a part.
+///
+/// The file is deleted when the `TempFile` is dropped, unless it was persisted.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    len: u64,
+}
+
+impl TempFile {
+    fn create(dir: &Path) -> io::Result<(Self, File)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let name = format!("mhttp-{}-{}-{}.part", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed), nanos);
+        let path = dir.join(name);
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+
+        Ok((Self { path, len: 0 }, file))
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Opens the file for reading.
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Moves the file to a permanent location, so it is not deleted anymore.
+    pub fn persist(self, to: impl AsRef<Path>) -> io::Result<()> {
+        let to = to.as_ref();
+        if fs::rename(&self.path, to).is_err() {
+            // The destination may be on another file system
+            fs::copy(&self.path, to)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A streaming parser of `multipart/form-data` bodies (RFC 7578).
+///
+/// Parts are read one by one, so large uploads can be written to temporary files
+/// without keeping the whole content in memory.
+///
+/// # Examples
+///
+/// ```
+/// use http::{Multipart, MultipartConfig};
+///
+/// let body = "--XyZ\r\n\
+///     Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+///     Content-Type: text/plain\r\n\
+///     \r\n\
+///     hello\r\n\
+///     --XyZ--\r\n";
+///
+/// let mut multipart = Multipart::new(body.as_bytes(), "XyZ", MultipartConfig::default());
+/// let part = multipart.next_part().unwrap().unwrap();
+///
+/// assert_eq!(part.filename.as_deref(), Some("a.txt"));
+/// assert_eq!(part.text().unwrap(), "hello");
+/// assert!(multipart.next_part().unwrap().is_none());
+/// ```
+pub struct Multipart<R> {
+    reader: R,
+    /// `\r\n--` followed by the boundary.
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    config: MultipartConfig,
+    total_bytes: usize,
+    parts: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> Multipart<R> {
+    /// Creates a parser reading the body from `reader`.
+    pub fn new(reader: R, boundary: &str, config: MultipartConfig) -> Self {
+        Self {
+            reader,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            buffer: Vec::new(),
+            config,
+            total_bytes: 0,
+            parts: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Reads the next part, returns `None` after the last one.
+    pub fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        if !self.started {
+            self.skip_preamble()?;
+            self.started = true;
+        }
+
+        // After a delimiter, `--` closes the body and anything else starts a new part
+        if !self.fill_to(2)? {
+            return Err(MultipartError::Malformed("unexpected end of body".to_string()));
+        }
+        if self.buffer.starts_with(b"--") {
+            self.finished = true;
+            return Ok(None);
+        }
+
+        self.parts += 1;
+        if self.parts > self.config.max_parts {
+            return Err(MultipartError::TooManyParts);
+        }
+
+        let headers = self.read_part_headers()?;
+        let (name, filename) = headers
+            .get("Content-Disposition")
+            .map(parse_disposition)
+            .unwrap_or((None, None));
+        let content_type = headers.get("Content-Type").map(str::to_string);
+        let data = self.read_part_content()?;
+
+        Ok(Some(Part { headers, name, filename, content_type, data }))
+    }
+
+    /// Skips everything before the first delimiter.
+    fn skip_preamble(&mut self) -> Result<(), MultipartError> {
+        // The first delimiter may be at the very beginning, without the leading CRLF
+        self.buffer.extend_from_slice(b"\r\n");
+
+        loop {
+            if let Some(pos) = find(&self.buffer, &self.delimiter) {
+                self.buffer.drain(..pos + self.delimiter.len());
+                return Ok(());
+            }
+
+            let keep = self.delimiter.len().min(self.buffer.len());
+            self.buffer.drain(..self.buffer.len() - keep);
+
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("missing boundary".to_string()));
+            }
+        }
+    }
+
+    /// Reads the headers of a part, the buffer starts right after the delimiter.
+    fn read_part_headers(&mut self) -> Result<HeaderMap, MultipartError> {
+        let end = loop {
+            // Transport padding after the boundary is ignored
+            let padding = self.buffer.iter().take_while(|&&b| b == b' ' || b == b'\t').count();
+            if let Some(pos) = find(&self.buffer[padding..], b"\r\n\r\n") {
+                if !self.buffer[padding..].starts_with(b"\r\n") {
+                    return Err(MultipartError::Malformed("invalid boundary line".to_string()));
+                }
+                break padding + pos;
+            }
+
+            if self.buffer.len() > MAX_PART_HEADER_BYTES {
+                return Err(MultipartError::Malformed("part headers too large".to_string()));
+            }
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of body".to_string()));
+            }
+        };
+
+        let block = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+        self.buffer.drain(..end + 4);
+
+        let mut headers = HeaderMap::new();
+        for line in block.split("\r\n").skip(1) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| MultipartError::Malformed(format!("invalid part header: {}", line)))?;
+            headers
+                .try_append(name.trim(), value.trim())
+                .map_err(|e| MultipartError::Malformed(e.to_string()))?;
+        }
+
+        Ok(headers)
+    }
+
+    /// Reads the content of a part up to and including the next delimiter.
+    fn read_part_content(&mut self) -> Result<PartData, MultipartError> {
+        let mut sink = Sink::Memory(Vec::new());
+
+        loop {
+            if let Some(pos) = find(&self.buffer, &self.delimiter) {
+                self.write(&mut sink, pos)?;
+                self.buffer.drain(..self.delimiter.len());
+                return sink.finish();
+            }
+
+            // Everything but a possible beginning of the delimiter belongs to the content
+            let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            self.write(&mut sink, safe)?;
+
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of body".to_string()));
+            }
+        }
+    }
+
+    /// Moves `len` bytes from the buffer into the sink, enforcing the limits.
+    fn write(&mut self, sink: &mut Sink, len: usize) -> Result<(), MultipartError> {
+        if sink.len() + len > self.config.max_part_bytes {
+            return Err(MultipartError::PartTooLarge);
+        }
+        self.total_bytes += len;
+        if self.total_bytes > self.config.max_total_bytes {
+            return Err(MultipartError::TooLarge);
+        }
+
+        sink.write(&self.buffer[..len], &self.config)?;
+        self.buffer.drain(..len);
+        Ok(())
+    }
+
+    /// Reads until the buffer holds at least `len` bytes, returns `false` at the end of the body.
+    fn fill_to(&mut self, len: usize) -> io::Result<bool> {
+        while self.buffer.len() < len {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Reads more of the body into the buffer, returns `false` at the end of the body.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0u8; READ_CHUNK_BYTES];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                    return Ok(true);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Multipart<R> {
+    type Item = Result<Part, MultipartError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_part();
+        if result.is_err() {
+            // A broken body cannot be resumed
+            self.finished = true;
+        }
+        result.transpose()
+    }
+}
+
+/// The content of a part while it is being read.
+enum Sink {
+    Memory(Vec<u8>),
+    File(TempFile, File),
+}
+
+impl Sink {
+    fn len(&self) -> usize {
+        match self {
+            Sink::Memory(bytes) => bytes.len(),
+            Sink::File(temp, _) => temp.len as usize,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8], config: &MultipartConfig) -> io::Result<()> {
+        if let Sink::Memory(memory) = self
+            && config.spill_threshold.is_some_and(|t| memory.len() + bytes.len() > t)
+        {
+            let (mut temp, mut file) = TempFile::create(&config.temp_dir)?;
+            file.write_all(memory)?;
+            temp.len = memory.len() as u64;
+            *self = Sink::File(temp, file);
+        }
+
+        match self {
+            Sink::Memory(memory) => memory.extend_from_slice(bytes),
+            Sink::File(temp, file) => {
+                file.write_all(bytes)?;
+                temp.len += bytes.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PartData, MultipartError> {
+        match self {
+            Sink::Memory(bytes) => Ok(PartData::Memory(bytes)),
+            Sink::File(temp, mut file) => {
+                file.flush()?;
+                Ok(PartData::File(temp))
+            }
+        }
+    }
+}
+
+/// Returns the `boundary` parameter of a `multipart/form-data` content type.
+pub(crate) fn boundary(content_type: Option<&str>) -> Result<String, MultipartError> {
+    let unsupported = || MultipartError::UnsupportedMediaType(content_type.map(str::to_string));
+    let content_type = content_type.ok_or_else(unsupported)?;
+
+    let mut params = split_params(content_type).into_iter();
+    let media_type = params.next().map(|(name, _)| name).unwrap_or_default();
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return Err(unsupported());
+    }
+
+    params
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .and_then(|(_, value)| value)
+        .filter(|b| !b.is_empty() && b.len() <= 70)
+        .ok_or(MultipartError::MissingBoundary)
+}
+
+/// Returns the `name` and `filename` parameters of a `Content-Disposition` header.
+fn parse_disposition(value: &str) -> (Option<String>, Option<String>) {
+    let params = split_params(value);
+    let get = |key: &str| {
+        params
+            .iter()
+            .skip(1)
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .and_then(|(_, value)| value.clone())
+    };
+    (get("name"), get("filename"))
+}
+
+/// Splits a header value like `form-data; name="a;b"` into its `;`-separated parameters.
+///
+/// Quoted values may contain `;` and backslash escapes.
+fn split_params(value: &str) -> Vec<(String, Option<String>)> {
+    let mut params = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    while chars.peek().is_some() {
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == ';' || c == '=' {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+
+        let mut param_value = None;
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+
+            let mut v = String::new();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => v.extend(chars.next()),
+                        c => v.push(c),
+                    }
+                }
+                // Skip anything between the closing quote and the next `;`
+                while chars.peek().is_some_and(|&c| c != ';') {
+                    chars.next();
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c == ';' {
+                        break;
+                    }
+                    v.push(c);
+                    chars.next();
+                }
+                v = v.trim().to_string();
+            }
+            param_value = Some(v);
+        }
+
+        chars.next(); // consume `;`
+        let name = name.trim().to_string();
+        if params.is_empty() || !name.is_empty() {
+            params.push((name, param_value));
+        }
+    }
+
+    params
+}
+
+/// Returns the position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
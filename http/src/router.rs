@@ -0,0 +1,535 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::app::{run_middlewares, AroundMiddleware, Handler, Middleware, MiddlewareResult, Next};
+use crate::constraint::Constraint;
+use crate::server::{HttpRequest, HttpResponse};
+use crate::urlencoded::percent_decode;
+
+/// A handler stored in the tree, shared by the routes an optional segment expands to.
+type SharedHandler = Arc<dyn Fn(HttpRequest) -> HttpResponse + Send + Sync>;
+
+/// The handlers of a route, by method.
+type MethodMap = HashMap<String, SharedHandler>;
+
+/// The method key of handlers registered for every method.
+pub(crate) const ANY_METHOD: &str = "*";
+
+/// The methods a request can have, in the order they are listed in `Allow` headers.
+const METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+
+/// A segment of a route pattern.
+enum Segment {
+    /// A literal segment, e.g. `users`.
+    Static(String),
+    /// A whole-segment parameter, e.g. `{id}`, `{id:u64}` or `{page?}`.
+    Param {
+        name: String,
+        constraint: Option<Constraint>,
+        optional: bool,
+    },
+    /// A parameter capturing the rest of the path, e.g. `{*path}`.
+    CatchAll(String),
+}
+
+impl Segment {
+    fn is_optional(&self) -> bool {
+        matches!(self, Segment::Param { optional: true, .. })
+    }
+}
+
+/// Splits a route pattern into segments.
+///
+/// # Panics
+///
+/// Panics if a parameter has no name or an invalid constraint, or a constraint ending in `?`, if a catch-all parameter
+/// is not the last segment, or if an optional parameter is followed by a required segment.
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let invalid = |reason: &str| invalid_route(pattern, reason);
+
+    let parts: Vec<&str> = pattern.strip_prefix('/').unwrap_or(pattern).split('/').collect();
+    let last = parts.len() - 1;
+
+    let segments: Vec<Segment> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let Some(inner) = part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) else {
+                return Segment::Static(part.to_string());
+            };
+
+            if let Some(name) = inner.strip_prefix('*') {
+                if i != last {
+                    invalid("a catch-all parameter must be the last segment");
+                }
+                if name.is_empty() || name.contains([':', '?']) {
+                    invalid("a catch-all parameter needs a plain name");
+                }
+                return Segment::CatchAll(name.to_string());
+            }
+
+            let (name, constraint) = match inner.split_once(':') {
+                Some((name, constraint)) => {
+                    if let Some(source) = constraint.strip_suffix('?') {
+                        invalid(&format!(
+                            "an optional parameter is written `{{{}?:{}}}`, a regex ending in `?` must be wrapped in a group",
+                            name.trim_end_matches('?'), source
+                        ));
+                    }
+                    (name, Some(Constraint::parse(constraint).unwrap_or_else(|e| invalid(&e))))
+                }
+                None => (inner, None),
+            };
+            let (name, optional) = match name.strip_suffix('?') {
+                Some(name) => (name, true),
+                None => (name, false),
+            };
+            if name.is_empty() {
+                invalid("a parameter needs a name");
+            }
+            Segment::Param { name: name.to_string(), constraint, optional }
+        })
+        .collect();
+
+    let first_optional = segments.iter().position(Segment::is_optional).unwrap_or(segments.len());
+    if segments[first_optional..].iter().any(|s| !s.is_optional()) {
+        invalid("optional parameters must be the last segments");
+    }
+    segments
+}
+
+fn invalid_route(pattern: &str, reason: &str) -> ! {
+    panic!("Invalid route `{}`: {}", pattern, reason)
+}
+
+/// An edge of the tree leading to the segments after a parameter.
+struct ParamEdge {
+    name: String,
+    constraint: Option<Constraint>,
+    node: Box<Node>,
+}
+
+/// A node of the route tree, one per path segment.
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    /// The parameter with a type constraint, the one with a regex and the unconstrained one, in this order.
+    params: Vec<ParamEdge>,
+    catch_all: Option<(String, Box<Node>)>,
+    /// The handlers of the route ending at this node, by method.
+    handlers: MethodMap,
+}
+
+impl Node {
+    /// Finds the route of the remaining segments, collecting the parameters on the way.
+    ///
+    /// Static segments are tried before parameters, and parameters before catch-all
+    /// parameters. If a branch does not lead to a route accepted by `accept`, the next one is tried.
+    fn find<'a>(&'a self, segments: &[&str], params: &mut Vec<(&'a str, String)>, accept: &dyn Fn(&MethodMap) -> bool) -> Option<&'a Node> {
+        let Some((first, rest)) = segments.split_first() else {
+            return (!self.handlers.is_empty() && accept(&self.handlers)).then_some(self);
+        };
+
+        if let Some(found) = self.statics.get(*first).and_then(|child| child.find(rest, params, accept)) {
+            return Some(found);
+        }
+
+        if !first.is_empty() {
+            for edge in &self.params {
+                if edge.constraint.as_ref().is_some_and(|c| !c.check(first)) {
+                    continue;
+                }
+                params.push((&edge.name, first.to_string()));
+                if let Some(found) = edge.node.find(rest, params, accept) {
+                    return Some(found);
+                }
+                params.pop();
+            }
+        }
+
+        if let Some((name, child)) = &self.catch_all {
+            let tail = segments.join("/");
+            if !tail.is_empty() && !child.handlers.is_empty() && accept(&child.handlers) {
+                params.push((name, tail));
+                return Some(child);
+            }
+        }
+
+        None
+    }
+
+    /// Adds the handler to every node from the `required`-th segment of the pattern on.
+    fn insert(&mut self, segments: &mut std::vec::IntoIter<Segment>, depth: usize, required: usize, method: &str, handler: &SharedHandler, pattern: &str) {
+        if depth >= required {
+            self.handlers.insert(method.to_string(), handler.clone());
+        }
+
+        let child = match segments.next() {
+            None => return,
+            Some(Segment::Static(s)) => self.statics.entry(s).or_default(),
+            Some(Segment::Param { name, constraint, .. }) => self.param_child(name, constraint, pattern),
+            Some(Segment::CatchAll(name)) => self.catch_all_child(name, pattern),
+        };
+        child.insert(segments, depth + 1, required, method, handler, pattern);
+    }
+
+    /// Returns the child behind a parameter, creating it if needed.
+    fn param_child(&mut self, name: String, constraint: Option<Constraint>, pattern: &str) -> &mut Node {
+        let source = constraint.as_ref().map(Constraint::source);
+        let existing = self.params.iter().position(|e| e.constraint.as_ref().map(Constraint::source) == source);
+
+        let index = match existing {
+            Some(index) if self.params[index].name != name => panic!(
+                "Route `{}` conflicts with an existing route: parameter `{}` is already registered as `{}`",
+                pattern, name, self.params[index].name
+            ),
+            Some(index) => index,
+            None => {
+                // Either could match a value, so which one wins would depend on the registration order
+                let is_type = |c: &Constraint| matches!(c, Constraint::Type(..));
+                if let Some(new) = &constraint
+                    && let Some(edge) = self.params.iter().find(|e| e.constraint.as_ref().is_some_and(|c| is_type(c) == is_type(new)))
+                {
+                    panic!(
+                        "Route `{}` conflicts with an existing route: parameter `{}:{}` overlaps with `{}:{}`",
+                        pattern, name, new.source(), edge.name, edge.constraint.as_ref().map_or("", Constraint::source)
+                    );
+                }
+
+                // The type constraint is tried first, the unconstrained parameter last
+                let index = match &constraint {
+                    Some(c) if is_type(c) => 0,
+                    Some(_) => self.params.iter().position(|e| e.constraint.is_none()).unwrap_or(self.params.len()),
+                    None => self.params.len(),
+                };
+                self.params.insert(index, ParamEdge { name, constraint, node: Box::default() });
+                index
+            }
+        };
+
+        &mut self.params[index].node
+    }
+
+    /// Returns the child behind a catch-all parameter, creating it if needed.
+    fn catch_all_child(&mut self, name: String, pattern: &str) -> &mut Node {
+        if let Some((existing, _)) = &self.catch_all
+            && *existing != name
+        {
+            panic!(
+                "Route `{}` conflicts with an existing route: parameter `{}` is already registered as `{}`",
+                pattern, name, existing
+            );
+        }
+        &mut self.catch_all.get_or_insert_with(|| (name, Box::default())).1
+    }
+}
+
+/// The routes of an application, compiled into a tree of path segments.
+///
+/// Looking up a path takes time proportional to its number of segments,
+/// not to the number of routes. When several routes match a path, the most
+/// specific one wins: a static segment beats a parameter, which beats a catch-all parameter.
+/// A segment may have one parameter with a type constraint, tried first, one with a regex
+/// and an unconstrained one, tried last.
+#[derive(Default)]
+pub(crate) struct RouteTree {
+    root: Node,
+}
+
+/// A route found for a path.
+pub(crate) struct RouteMatch<'a> {
+    handlers: &'a MethodMap,
+    /// The values of the path parameters.
+    pub(crate) params: HashMap<String, String>,
+}
+
+impl<'a> RouteMatch<'a> {
+    /// Returns the handler of a method.
+    ///
+    /// `HEAD` requests fall back to the `GET` handler, and every method falls back
+    /// to the handler registered for any method.
+    pub(crate) fn handler(&self, method: &str) -> Option<&'a SharedHandler> {
+        handler(self.handlers, method)
+    }
+
+    /// Returns the value of the `Allow` header of the route, e.g. `GET, HEAD, OPTIONS`.
+    pub(crate) fn allow(&self) -> String {
+        let mut allowed: Vec<&str> = METHODS
+            .into_iter()
+            .filter(|m| *m == "OPTIONS" || handler(self.handlers, m).is_some())
+            .collect();
+
+        // Methods outside of the usual ones are listed after them
+        let mut others: Vec<&str> = self
+            .handlers
+            .keys()
+            .map(String::as_str)
+            .filter(|m| *m != ANY_METHOD && !METHODS.contains(m))
+            .collect();
+        others.sort();
+        allowed.extend(others);
+
+        allowed.join(", ")
+    }
+}
+
+fn handler<'a>(handlers: &'a MethodMap, method: &str) -> Option<&'a SharedHandler> {
+    handlers
+        .get(method)
+        .or_else(|| if method == "HEAD" { handlers.get("GET") } else { None })
+        .or_else(|| handlers.get(ANY_METHOD))
+}
+
+impl RouteTree {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a route, replacing the handler of the same method and pattern.
+    ///
+    /// A pattern ending with optional parameters is added once for every number of them,
+    /// e.g. `/posts/{page?}` as `/posts` and `/posts/{page}`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is invalid, or if it conflicts with a registered route,
+    /// i.e. it names a parameter differently than another route at the same position
+    /// with the same constraint.
+    pub(crate) fn insert(&mut self, method: &str, pattern: &str, handler: Handler) {
+        let handler: SharedHandler = Arc::from(handler);
+        let segments = parse_pattern(pattern);
+        let required = segments.iter().position(Segment::is_optional).unwrap_or(segments.len());
+
+        // Paths have at least one segment, so `/` is reached through an empty static segment
+        if required == 0 {
+            self.root.statics.entry(String::new()).or_default().handlers.insert(method.to_string(), handler.clone());
+        }
+
+        self.root.insert(&mut segments.into_iter(), 0, required.max(1), method, &handler, pattern);
+    }
+
+    /// Finds the route of a raw path, as sent by the client.
+    ///
+    /// The path is split into segments before they are percent-decoded, so an encoded
+    /// slash (`%2F`) is part of a segment instead of separating two. Routes with a handler of the method are preferred, so `/users/me` without a `GET`
+    /// handler does not hide `/users/{id}` with one. If no route has a handler of the method,
+    /// the best route of the path is returned, so the caller can answer `405 Method Not Allowed`.
+    pub(crate) fn find(&self, path: &str, method: &str) -> Option<RouteMatch<'_>> {
+        let segments: Vec<String> = path.strip_prefix('/').unwrap_or(path).split('/').map(percent_decode).collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        let serves_method = |handlers: &MethodMap| handler(handlers, method).is_some();
+        [&serves_method as &dyn Fn(&MethodMap) -> bool, &|_: &MethodMap| true]
+            .into_iter()
+            .find_map(|accept| {
+                let mut params = Vec::new();
+                let node = self.root.find(&segments, &mut params, accept)?;
+                Some(RouteMatch {
+                    handlers: &node.handlers,
+                    params: params.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+                })
+            })
+    }
+}
+
+/// A set of routes built independently of an [`App`](crate::App), e.g. by a module of a service.
+///
+/// The routes are added to an application under a prefix with [`App::nest`](crate::App::nest),
+/// or to another router with [`Router::nest`]. The prefix may contain parameters,
+/// their values are passed to the handlers along with the parameters of the route.
+///
+/// # Examples
+///
+/// ```
+/// use http::{App, HttpRequest, Respondable, Router};
+///
+/// let mut users = Router::new();
+/// users.get("/".to_string(), |_| "All users".into_response());
+/// users.get("/{id:u64}".to_string(), |req: HttpRequest| {
+///     let version: String = req.path_param("version").unwrap();
+///     let id: u64 = req.path_param("id").unwrap();
+///     format!("User {} ({})", id, version).into_response()
+/// });
+///
+/// let mut app = App::new();
+/// // Serves `/api/v1/users` and `/api/v1/users/7`
+/// app.nest("/api/{version}/users", users);
+/// ```
+///
+/// A router with middlewares is a route group: they only run for its routes,
+/// see [`Router::use_middleware`].
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<(String, String, Handler)>,
+    middlewares: Vec<Middleware>,
+    /// The middlewares added with [`Router::wrap`], with the number of middlewares added before them.
+    around: Vec<(usize, AroundMiddleware)>,
+}
+
+impl Router {
+    /// Creates a router without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for `GET` requests, see [`App::get`](crate::App::get).
+    pub fn get<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("GET", path, Box::new(handler));
+    }
+
+    /// Registers a handler for `POST` requests, see [`App::post`](crate::App::post).
+    pub fn post<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("POST", path, Box::new(handler));
+    }
+
+    /// Registers a handler for `PUT` requests, see [`App::put`](crate::App::put).
+    pub fn put<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("PUT", path, Box::new(handler));
+    }
+
+    /// Registers a handler for `DELETE` requests, see [`App::delete`](crate::App::delete).
+    pub fn delete<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("DELETE", path, Box::new(handler));
+    }
+
+    /// Registers a handler for `PATCH` requests, see [`App::patch`](crate::App::patch).
+    pub fn patch<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("PATCH", path, Box::new(handler));
+    }
+
+    /// Registers a handler for `HEAD` requests, see [`App::head`](crate::App::head).
+    pub fn head<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("HEAD", path, Box::new(handler));
+    }
+
+    /// Registers a handler for `OPTIONS` requests, see [`App::options`](crate::App::options).
+    pub fn options<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add("OPTIONS", path, Box::new(handler));
+    }
+
+    /// Registers a handler for every method, see [`App::any`](crate::App::any).
+    pub fn any<F>(&mut self, path: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add(ANY_METHOD, path, Box::new(handler));
+    }
+
+    /// Registers a handler with path parameters, see [`App::add_param_handler`](crate::App::add_param_handler).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is invalid. Conflicts with other routes are only detected
+    /// once the router is nested.
+    pub fn add_param_handler<F>(&mut self, path: String, method: String, handler: F)
+    where
+        F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.add(&method, path, Box::new(handler));
+    }
+
+    /// Adds a middleware which only runs for the routes of the router.
+    ///
+    /// The middlewares of a router run after the route has matched, so they can read
+    /// `req.path_params`, and after the middlewares of the `App` and of the routers
+    /// it is nested in. Requests answered automatically, i.e. `404 Not Found`,
+    /// `405 Method Not Allowed` and `OPTIONS` without a handler, do not run them.
+    pub fn use_middleware<F>(&mut self, md: F)
+    where
+        F: Fn(HttpRequest) -> MiddlewareResult + Send + Sync + 'static,
+    {
+        self.middlewares.push(Box::new(md));
+    }
+
+    /// Adds a middleware which runs around the rest of the chain, see [`App::wrap`](crate::App::wrap).
+    ///
+    /// Like [`Router::use_middleware`], it only runs for the routes of the router.
+    pub fn wrap<F>(&mut self, md: F)
+    where
+        F: Fn(HttpRequest, Next<'_>) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.around.push((self.middlewares.len(), Box::new(md)));
+    }
+
+    /// Adds the routes of another router under a prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix is invalid, e.g. it contains an optional or catch-all parameter.
+    pub fn nest(&mut self, prefix: &str, router: Router) {
+        for (method, path, handler) in router.into_routes() {
+            self.add(&method, join_paths(prefix, &path), handler);
+        }
+    }
+
+    /// Returns the number of routes.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Returns `true` if there are no routes.
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    fn add(&mut self, method: &str, path: String, handler: Handler) {
+        // Report invalid patterns where they are written, not where the router is nested
+        parse_pattern(&path);
+        self.routes.push((method.to_string(), path, handler));
+    }
+
+    /// Returns the routes, with their handlers wrapped in the middlewares of the router.
+    fn into_routes(self) -> Vec<(String, String, Handler)> {
+        if self.middlewares.is_empty() && self.around.is_empty() {
+            return self.routes;
+        }
+
+        let chain = Arc::new((self.middlewares, self.around));
+        self.routes
+            .into_iter()
+            .map(|(method, path, handler)| {
+                let chain = Arc::clone(&chain);
+                let handler: Handler = Box::new(move |req| run_middlewares(&chain.0, &chain.1, req, &handler));
+                (method, path, handler)
+            })
+            .collect()
+    }
+
+    /// Adds the routes to a tree under a prefix.
+    pub(crate) fn mount(self, prefix: &str, tree: &mut RouteTree) {
+        for (method, path, handler) in self.into_routes() {
+            tree.insert(&method, &join_paths(prefix, &path), handler);
+        }
+    }
+}
+
+/// Joins a prefix and a path, the root path of a router being the prefix itself.
+fn join_paths(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    let path = path.trim_start_matches('/');
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => format!("/{}", path),
+        (false, true) => format!("/{}", prefix),
+        (false, false) => format!("/{}/{}", prefix, path),
+    }
+}
@@ -1,4 +1,12 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io::{self, Read, Write}, sync::{Arc, Mutex}};
+
+use crate::headers::HeaderMap;
+use crate::urlencoded::{self, FormError, Params};
+use crate::multipart::{self, Multipart, MultipartConfig, MultipartError};
+use crate::cookie::{Cookie, CookieJar};
+use crate::extensions::Extensions;
+use crate::session::Session;
+use crate::error::HttpError;
 
 
 
@@ -7,15 +15,27 @@ use std::collections::HashMap;
 /// This struct contains all the information from an HTTP request including the method,
 /// URI, headers, and body. It's passed to handler functions for processing.
 /// 
+/// The `uri` is the request target as sent by the client, while `path` and `query`
+/// are its two parts. Routes are matched against the segments of the path, each one
+/// percent-decoded on its own, so `/files/a%2Fb` matches `/files/{name}` with `name` set to `a/b`.
+/// 
 #[derive(Debug, Clone)]
 pub struct HttpRequest {
     pub method:  String,
     pub uri: String,
+    /// The percent-decoded path of the URI, e.g. `/files/my file.txt`.
+    pub path: String,
+    /// The raw query string of the URI without the `?`, e.g. `q=rust&page=2`.
+    pub query: String,
     pub version: String,
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
     pub content_length: usize,
-    pub body: String,
-    pub path_params: HashMap<String,String>
+    pub body: Vec<u8>,
+    pub path_params: HashMap<String,String>,
+    /// The decoded query parameters, a key can have several values.
+    pub query_params: Params,
+    /// Values attached to the request by middlewares.
+    pub extensions: Extensions,
 }
 
 const SP: char = ' ';
@@ -27,6 +47,7 @@ pub enum ParseError {
     InvalidMethod(String),
     InvalidUri(String),
     InvalidVersion(String),
+    InvalidHeader(String),
     MalformedRequest,
 }
 
@@ -36,6 +57,7 @@ impl std::fmt::Display for ParseError {
             ParseError::InvalidMethod(m) => write!(f, "Invalid HTTP method: {}", m),
             ParseError::InvalidUri(u) => write!(f, "Invalid URI: {}", u),
             ParseError::InvalidVersion(v) => write!(f, "Invalid HTTP version: {}", v),
+            ParseError::InvalidHeader(h) => write!(f, "Invalid header: {}", h),
             ParseError::MalformedRequest => write!(f, "Malformed HTTP request"),
         }
     }
@@ -58,37 +80,205 @@ impl HttpRequest {
         let (method, uri, version) = Self::parse_request_line(request_line)?;
 
         // Parse headers
-        let mut headers = HashMap::new();
+        let mut headers = HeaderMap::new();
 
         for line in lines.by_ref() {
             if line.is_empty() {
                 break; // End of headers
             }
             let colon_pos = line.find(':').ok_or(ParseError::MalformedRequest)?;
-            let header_name = line[..colon_pos].trim().to_string();
+            let header_name = line[..colon_pos].to_string();
             let header_value = line[colon_pos + 1..].trim().to_string();
-            headers.insert(header_name, header_value);
+            headers
+                .try_append(header_name, header_value)
+                .map_err(|e| ParseError::InvalidHeader(e.to_string()))?;
         }
 
-        let content_length = headers
-            .get("Content-Length")
-            .and_then(|s| s.parse::<usize>().ok())
-            .unwrap_or(0);
+        let content_length = Self::parse_content_length(&headers)?;
 
         // Collect remaining lines as body
-        let body = lines.collect::<Vec<_>>().join("\n");
+        let body = lines.collect::<Vec<_>>().join("\n").into_bytes();
+
+        // Split the target into the path and the query string
+        let (raw_path, query) = uri.split_once('?').unwrap_or((&uri, ""));
+        let path = urlencoded::percent_decode(raw_path);
+        let query_params = Params::parse(query);
+        let query = query.to_string();
 
         Ok(HttpRequest {
             method,
             uri,
+            path,
+            query,
             version,
             headers,
             content_length,
             body,
-            path_params:HashMap::new()
+            path_params:HashMap::new(),
+            query_params,
+            extensions: Extensions::new(),
         })
     }
 
+    /// Returns the value of the `Content-Length` headers, `0` if there is none.
+    /// 
+    /// A length may be repeated, in several headers or as a list, but all values must
+    /// be the same. Otherwise the end of the body would be unclear, and a proxy in front
+    /// of the server could see another request in it (RFC 9112, section 6.3).
+    fn parse_content_length(headers: &HeaderMap) -> Result<usize, ParseError> {
+        let mut content_length = None;
+        for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+            let value = value.trim();
+            let length = value
+                .parse::<usize>()
+                .ok()
+                .filter(|_| value.bytes().all(|b| b.is_ascii_digit()))
+                .ok_or_else(|| ParseError::InvalidHeader(format!("Content-Length: {}", value)))?;
+            if content_length.is_some_and(|previous| previous != length) {
+                return Err(ParseError::InvalidHeader("Conflicting Content-Length headers".to_string()));
+            }
+            content_length = Some(length);
+        }
+        Ok(content_length.unwrap_or(0))
+    }
+
+    /// Returns the path of the URI as sent by the client, without percent-decoding.
+    pub fn raw_path(&self) -> &str {
+        self.uri.split_once('?').map_or(self.uri.as_str(), |(path, _)| path)
+    }
+
+    /// Returns the body as text.
+    /// 
+    /// Fails if the body is not valid UTF-8.
+    pub fn text(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    /// Returns the body as text, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn text_lossy(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Returns a path parameter parsed into a type, e.g. a `{id:u64}` as `u64`.
+    /// 
+    /// Returns `None` if the parameter is missing or does not parse.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{App, HttpRequest, Respondable};
+    /// 
+    /// let mut app = App::new();
+    /// app.get("/users/{id:u64}".to_string(), |req: HttpRequest| {
+    ///     let id: u64 = req.path_param("id").unwrap();
+    ///     format!("User {}", id).into_response()
+    /// });
+    /// ```
+    pub fn path_param<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.path_params.get(name)?.parse().ok()
+    }
+
+    /// Returns the cookies sent in the `Cookie` headers.
+    pub fn cookies(&self) -> CookieJar {
+        CookieJar::from_headers(&self.headers)
+    }
+
+    /// Returns the session of the request, `None` if the session middleware is not used.
+    pub fn session(&self) -> Option<Session> {
+        self.extensions.get::<Session>().cloned()
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` body.
+    /// 
+    /// Fails if the request has another content type or the body is not valid UTF-8.
+    /// Together with `#[derive(Jsonable)]`, the fields can be decoded straight into a struct.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{HttpRequest, Jsonable};
+    /// 
+    /// #[derive(Jsonable)]
+    /// struct Login {
+    ///     user: String,
+    ///     remember: bool,
+    /// }
+    /// 
+    /// let req = HttpRequest::parse(
+    ///     "POST /login HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\nuser=J%C3%BCrgen&remember=on"
+    /// ).unwrap();
+    /// 
+    /// let login = Login::from_form(&req.form().unwrap()).unwrap();
+    /// assert_eq!(login.user, "Jürgen");
+    /// assert!(login.remember);
+    /// ```
+    pub fn form(&self) -> Result<Params, FormError> {
+        let content_type = self.headers.get("Content-Type");
+        let media_type = content_type.map(|t| t.split(';').next().unwrap_or("").trim());
+
+        match media_type {
+            Some(t) if t.eq_ignore_ascii_case("application/x-www-form-urlencoded") => (),
+            _ => return Err(FormError::UnsupportedMediaType(content_type.map(str::to_string))),
+        }
+
+        let body = std::str::from_utf8(&self.body).map_err(|_| FormError::InvalidBody)?;
+        Ok(Params::parse(body))
+    }
+
+    /// Returns a parser of a `multipart/form-data` body with the default [`MultipartConfig`].
+    /// 
+    /// Fails if the request has another content type or no boundary.
+    /// The body is already in memory, so uploads are limited by `ServerConfig::max_body_bytes`.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use http::{HttpRequest, HttpResponse, Respondable, StatusCode};
+    /// 
+    /// fn upload(req: HttpRequest) -> HttpResponse {
+    ///     let parts = match req.multipart() {
+    ///         Ok(parts) => parts,
+    ///         Err(e) => return HttpResponse::new(StatusCode::BadRequest, e.to_string()),
+    ///     };
+    /// 
+    ///     let mut names = Vec::new();
+    ///     for part in parts {
+    ///         match part {
+    ///             Ok(part) => names.extend(part.filename),
+    ///             Err(e) => return HttpResponse::new(StatusCode::BadRequest, e.to_string()),
+    ///         }
+    ///     }
+    ///     names.join(", ").into_response()
+    /// }
+    /// ```
+    pub fn multipart(&self) -> Result<Multipart<&[u8]>, MultipartError> {
+        self.multipart_with(MultipartConfig::default())
+    }
+
+    /// Returns a parser of a `multipart/form-data` body with the given limits.
+    pub fn multipart_with(&self, config: MultipartConfig) -> Result<Multipart<&[u8]>, MultipartError> {
+        let boundary = multipart::boundary(self.headers.get("Content-Type"))?;
+        Ok(Multipart::new(&self.body[..], &boundary, config))
+    }
+
+    /// Returns `true` if the client wants the connection to stay open after this request.
+    /// 
+    /// HTTP/1.1 connections are persistent unless `Connection: close` is sent,
+    /// HTTP/1.0 connections are persistent only with `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.headers
+                .get_all("Connection")
+                .flat_map(|v| v.split(','))
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+
+        match self.version.as_str() {
+            "HTTP/1.0" => has_token("keep-alive"),
+            _ => !has_token("close"),
+        }
+    }
+
     fn parse_request_line(line: &str) -> Result<(String, String, String), ParseError> {
         let mut parts = line.split(SP);
         let method = parts.next().ok_or(ParseError::MalformedRequest)?.to_string();
@@ -134,70 +324,449 @@ impl HttpRequest {
 
 
 
-/// Represents an HTTP status code.
-/// 
-/// This enum provides type-safe access to common HTTP status codes.
-/// Each variant corresponds to a specific status code with its associated meaning.
-/// 
-/// # Examples
-/// 
-/// ```
-/// use your_crate::StatusCode;
-/// 
-/// let ok = StatusCode::Ok;
-/// assert_eq!(ok.as_u16(), 200);
-/// assert_eq!(ok.reason_phrase(), "OK");
-/// ```
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum StatusCode {
+/// Declares the `StatusCode` enum together with the numeric values and reason phrases of its variants.
+macro_rules! status_codes {
+    ($( $(#[$docs:meta])* ($code:literal, $variant:ident, $phrase:literal); )+) => {
+        /// Represents an HTTP status code.
+        /// 
+        /// This enum provides type-safe access to every status code of the IANA registry.
+        /// Each variant corresponds to a specific status code with its associated meaning,
+        /// non-standard codes can be represented with `StatusCode::Custom`.
+        /// 
+        /// # Examples
+        /// 
+        /// ```
+        /// use http::StatusCode;
+        /// 
+        /// let ok = StatusCode::Ok;
+        /// assert_eq!(ok.as_u16(), 200);
+        /// assert_eq!(ok.reason_phrase(), "OK");
+        /// ```
+        #[derive(Debug, Clone, Copy)]
+        pub enum StatusCode {
+            $(
+                $(#[$docs])*
+                $variant,
+            )+
+            /// A status code which is not in the IANA registry, e.g. `599`.
+            /// 
+            /// The code must have three digits, i.e. be in `100..=999`, [`StatusCode::custom`]
+            /// checks it. A response with a code outside of this range is not written.
+            /// The reason phrase of a custom code is empty, set `HttpResponse::status_message` to send one.
+            Custom(u16),
+        }
+
+        impl StatusCode {
+            /// Returns the numeric value of the status code.
+            /// 
+            /// # Examples
+            /// 
+            /// ```
+            /// use http::StatusCode;
+            /// 
+            /// assert_eq!(StatusCode::Ok.as_u16(), 200);
+            /// ```
+            pub fn as_u16(&self) -> u16 {
+                match self {
+                    $( StatusCode::$variant => $code, )+
+                    StatusCode::Custom(code) => *code,
+                }
+            }
+
+            /// Returns the reason phrase associated with the status code.
+            /// 
+            /// # Examples
+            /// 
+            /// ```
+            /// use http::StatusCode;
+            /// 
+            /// assert_eq!(StatusCode::Ok.reason_phrase(), "OK");
+            /// ```
+            pub fn reason_phrase(&self) -> &'static str {
+                match self {
+                    $( StatusCode::$variant => $phrase, )+
+                    StatusCode::Custom(_) => "",
+                }
+            }
+
+            /// Creates a status code from its numeric value.
+            /// 
+            /// Registered codes are mapped to their named variant, other three-digit codes
+            /// are mapped to `StatusCode::Custom`. Returns `None` for values outside of `100..=999`.
+            /// 
+            /// # Examples
+            /// 
+            /// ```
+            /// use http::StatusCode;
+            /// 
+            /// assert_eq!(StatusCode::from_u16(201), Some(StatusCode::Created));
+            /// assert_eq!(StatusCode::from_u16(599), Some(StatusCode::Custom(599)));
+            /// assert_eq!(StatusCode::from_u16(42), None);
+            /// ```
+            pub fn from_u16(code: u16) -> Option<Self> {
+                match code {
+                    $( $code => Some(StatusCode::$variant), )+
+                    _ => Self::custom(code),
+                }
+            }
+
+            /// Creates a `StatusCode::Custom` status code.
+            /// 
+            /// Returns `None` for values outside of `100..=999`.
+            /// 
+            /// # Examples
+            /// 
+            /// ```
+            /// use http::StatusCode;
+            /// 
+            /// assert_eq!(StatusCode::custom(599), Some(StatusCode::Custom(599)));
+            /// assert_eq!(StatusCode::custom(1000), None);
+            /// ```
+            pub fn custom(code: u16) -> Option<Self> {
+                match code {
+                    100..=999 => Some(StatusCode::Custom(code)),
+                    _ => None,
+                }
+            }
+
+            /// Returns `true` if the numeric value has three digits, which is
+            /// only `false` for a `StatusCode::Custom` built without [`StatusCode::custom`].
+            pub fn is_valid(&self) -> bool {
+                (100..=999).contains(&self.as_u16())
+            }
+        }
+    };
+}
+
+status_codes! {
+    /// 100 Continue - The client should continue with its request
+    (100, Continue, "Continue");
+    /// 101 Switching Protocols - The server is switching to the protocol requested in the `Upgrade` header
+    (101, SwitchingProtocols, "Switching Protocols");
+    /// 102 Processing - The server has accepted the request but has not completed it yet
+    (102, Processing, "Processing");
+    /// 103 Early Hints - Headers sent ahead of the final response, e.g. to preload resources
+    (103, EarlyHints, "Early Hints");
     /// 200 OK - Standard response for successful HTTP requests
-    Ok = 200,
-    /// 404 Not Found - The requested resource could not be found
-    NotFound = 404,
+    (200, Ok, "OK");
+    /// 201 Created - The request has been fulfilled and a new resource has been created
+    (201, Created, "Created");
+    /// 202 Accepted - The request has been accepted for processing, but the processing has not been completed
+    (202, Accepted, "Accepted");
+    /// 203 Non-Authoritative Information - The returned content was modified by a transforming proxy
+    (203, NonAuthoritativeInformation, "Non-Authoritative Information");
+    /// 204 No Content - The request succeeded and there is no content to send
+    (204, NoContent, "No Content");
+    /// 205 Reset Content - The request succeeded and the client should reset the document view
+    (205, ResetContent, "Reset Content");
+    /// 206 Partial Content - Only a part of the resource is sent, as requested by a `Range` header
+    (206, PartialContent, "Partial Content");
+    /// 207 Multi-Status - The body contains several status codes for several operations (WebDAV)
+    (207, MultiStatus, "Multi-Status");
+    /// 208 Already Reported - The members of a binding have already been listed (WebDAV)
+    (208, AlreadyReported, "Already Reported");
+    /// 226 IM Used - The response is the result of instance-manipulations applied to the resource
+    (226, ImUsed, "IM Used");
+    /// 300 Multiple Choices - The resource has several representations to choose from
+    (300, MultipleChoices, "Multiple Choices");
+    /// 301 Moved Permanently - The resource has been moved to the URL in the `Location` header
+    (301, MovedPermanently, "Moved Permanently");
+    /// 302 Found - The resource temporarily resides at the URL in the `Location` header
+    (302, Found, "Found");
+    /// 303 See Other - The response can be found at the URL in the `Location` header using `GET`
+    (303, SeeOther, "See Other");
+    /// 304 Not Modified - The resource has not been modified since the version specified by the request
+    (304, NotModified, "Not Modified");
+    /// 305 Use Proxy - The resource must be accessed through a proxy (deprecated)
+    (305, UseProxy, "Use Proxy");
+    /// 307 Temporary Redirect - The request should be repeated with the same method at another URL
+    (307, TemporaryRedirect, "Temporary Redirect");
+    /// 308 Permanent Redirect - The request and all future requests should use another URL with the same method
+    (308, PermanentRedirect, "Permanent Redirect");
     /// 400 Bad Request - The server cannot or will not process the request due to an apparent client error
-    BadRequest = 400,
+    (400, BadRequest, "Bad Request");
+    /// 401 Unauthorized - Authentication is required and has failed or has not been provided
+    (401, Unauthorized, "Unauthorized");
+    /// 402 Payment Required - Reserved for future use
+    (402, PaymentRequired, "Payment Required");
+    /// 403 Forbidden - The client is not allowed to access the resource
+    (403, Forbidden, "Forbidden");
+    /// 404 Not Found - The requested resource could not be found
+    (404, NotFound, "Not Found");
+    /// 405 Method Not Allowed - The request method is not supported by the resource
+    (405, MethodNotAllowed, "Method Not Allowed");
+    /// 406 Not Acceptable - No representation matches the `Accept` headers of the request
+    (406, NotAcceptable, "Not Acceptable");
+    /// 407 Proxy Authentication Required - The client must authenticate with the proxy
+    (407, ProxyAuthenticationRequired, "Proxy Authentication Required");
+    /// 408 Request Timeout - The client did not send a complete request in time
+    (408, RequestTimeout, "Request Timeout");
+    /// 409 Conflict - The request conflicts with the current state of the resource
+    (409, Conflict, "Conflict");
+    /// 410 Gone - The resource is no longer available and will not be available again
+    (410, Gone, "Gone");
+    /// 411 Length Required - The request did not specify the length of its content
+    (411, LengthRequired, "Length Required");
+    /// 412 Precondition Failed - A precondition given in the request headers was not met
+    (412, PreconditionFailed, "Precondition Failed");
+    /// 413 Content Too Large - The request body is larger than the server is willing to process
+    (413, ContentTooLarge, "Content Too Large");
+    /// 414 URI Too Long - The request target is longer than the server is willing to interpret
+    (414, UriTooLong, "URI Too Long");
+    /// 415 Unsupported Media Type - The media type of the request body is not supported
+    (415, UnsupportedMediaType, "Unsupported Media Type");
+    /// 416 Range Not Satisfiable - The `Range` header of the request cannot be fulfilled
+    (416, RangeNotSatisfiable, "Range Not Satisfiable");
+    /// 417 Expectation Failed - The `Expect` header of the request cannot be met
+    (417, ExpectationFailed, "Expectation Failed");
+    /// 421 Misdirected Request - The request was directed at a server which cannot produce a response
+    (421, MisdirectedRequest, "Misdirected Request");
+    /// 422 Unprocessable Content - The request is well-formed but contains semantic errors
+    (422, UnprocessableContent, "Unprocessable Content");
+    /// 423 Locked - The resource is locked (WebDAV)
+    (423, Locked, "Locked");
+    /// 424 Failed Dependency - The request failed because a request it depends on failed (WebDAV)
+    (424, FailedDependency, "Failed Dependency");
+    /// 425 Too Early - The server is unwilling to process a request which might be replayed
+    (425, TooEarly, "Too Early");
+    /// 426 Upgrade Required - The client should switch to the protocol in the `Upgrade` header
+    (426, UpgradeRequired, "Upgrade Required");
+    /// 428 Precondition Required - The server requires the request to be conditional
+    (428, PreconditionRequired, "Precondition Required");
+    /// 429 Too Many Requests - The client has sent too many requests in a given amount of time
+    (429, TooManyRequests, "Too Many Requests");
+    /// 431 Request Header Fields Too Large - The request headers are larger than the server is willing to process
+    (431, RequestHeaderFieldsTooLarge, "Request Header Fields Too Large");
+    /// 451 Unavailable For Legal Reasons - The resource cannot be provided for legal reasons
+    (451, UnavailableForLegalReasons, "Unavailable For Legal Reasons");
     /// 500 Internal Server Error - A generic error message when the server encounters an unexpected condition
-    InternalServerError = 500,
-    // Add more as needed
+    (500, InternalServerError, "Internal Server Error");
+    /// 501 Not Implemented - The server does not support the functionality required to fulfil the request
+    (501, NotImplemented, "Not Implemented");
+    /// 502 Bad Gateway - The server, acting as a gateway, received an invalid response from upstream
+    (502, BadGateway, "Bad Gateway");
+    /// 503 Service Unavailable - The server is temporarily unable to handle the request
+    (503, ServiceUnavailable, "Service Unavailable");
+    /// 504 Gateway Timeout - The server, acting as a gateway, did not receive a response from upstream in time
+    (504, GatewayTimeout, "Gateway Timeout");
+    /// 505 HTTP Version Not Supported - The HTTP version used in the request is not supported
+    (505, HttpVersionNotSupported, "HTTP Version Not Supported");
+    /// 506 Variant Also Negotiates - Transparent content negotiation results in a circular reference
+    (506, VariantAlsoNegotiates, "Variant Also Negotiates");
+    /// 507 Insufficient Storage - The server is unable to store the representation needed to complete the request (WebDAV)
+    (507, InsufficientStorage, "Insufficient Storage");
+    /// 508 Loop Detected - The server detected an infinite loop while processing the request (WebDAV)
+    (508, LoopDetected, "Loop Detected");
+    /// 510 Not Extended - Further extensions to the request are required
+    (510, NotExtended, "Not Extended");
+    /// 511 Network Authentication Required - The client needs to authenticate to gain network access
+    (511, NetworkAuthenticationRequired, "Network Authentication Required");
 }
 
 impl StatusCode {
-    /// Returns the numeric value of the status code.
-    /// 
-    /// # Examples
-    /// 
-    /// ```
-    /// use your_crate::StatusCode;
-    /// 
-    /// assert_eq!(StatusCode::Ok.as_u16(), 200);
-    /// ```
-    pub fn as_u16(&self) -> u16 {
-        *self as u16
+    /// Returns `true` for `1xx` informational codes.
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.as_u16())
     }
 
-    /// Returns the reason phrase associated with the status code.
-    /// 
-    /// # Examples
+    /// Returns `true` for `2xx` success codes.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+
+    /// Returns `true` for `3xx` redirection codes.
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.as_u16())
+    }
+
+    /// Returns `true` for `4xx` client error codes.
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+
+    /// Returns `true` for `5xx` server error codes.
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+}
+
+/// Status codes are equal when their numeric values are equal,
+/// so `StatusCode::Custom(200)` equals `StatusCode::Ok`.
+impl PartialEq for StatusCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_u16() == other.as_u16()
+    }
+}
+
+impl Eq for StatusCode {}
+
+impl std::hash::Hash for StatusCode {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_u16().hash(state);
+    }
+}
+
+impl std::fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason_phrase() {
+            "" => write!(f, "{}", self.as_u16()),
+            phrase => write!(f, "{} {}", self.as_u16(), phrase),
+        }
+    }
+}
+
+/// The body of an HTTP response.
+/// 
+/// A body is either fully kept in memory and sent with a `Content-Length` header,
+/// or produced incrementally and sent with `Transfer-Encoding: chunked`.
+#[derive(Clone)]
+pub enum Body {
+    /// A body which is fully in memory.
+    Full(Vec<u8>),
+    /// A body produced chunk by chunk.
     /// 
-    /// ```
-    /// use your_crate::StatusCode;
+    /// An error stops the response and closes the connection.
+    Stream(BodyStream),
+}
+
+/// The chunks of a streamed body.
+/// 
+/// Clones share the same stream, so the chunks go to whichever clone is read first:
+/// a cloned streamed response can only be sent once.
+#[derive(Clone)]
+pub struct BodyStream {
+    chunks: Arc<Mutex<Chunks>>,
+}
+
+type Chunks = Box<dyn Iterator<Item = io::Result<Vec<u8>>> + Send>;
+
+impl BodyStream {
+    /// Creates a stream from an iterator over the chunks of the body.
+    pub fn new<I>(chunks: I) -> Self
+    where
+        I: Iterator<Item = io::Result<Vec<u8>>> + Send + 'static,
+    {
+        Self { chunks: Arc::new(Mutex::new(Box::new(chunks))) }
+    }
+}
+
+impl Iterator for BodyStream {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chunks.lock().unwrap_or_else(|e| e.into_inner()).next()
+    }
+}
+
+impl std::fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodyStream").finish_non_exhaustive()
+    }
+}
+
+impl Body {
+    /// Returns `true` if the body is produced incrementally.
+    pub fn is_stream(&self) -> bool {
+        matches!(self, Body::Stream(_))
+    }
+
+    /// Returns the bytes of an in-memory body, or `None` for a streamed body.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Full(body) => Some(body),
+            Body::Stream(_) => None,
+        }
+    }
+
+    /// Returns an in-memory body as text.
     /// 
-    /// assert_eq!(StatusCode::Ok.reason_phrase(), "OK");
-    /// ```
-    pub fn reason_phrase(&self) -> &'static str {
+    /// Returns `None` for a streamed body or a body which is not valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        self.as_bytes().and_then(|body| std::str::from_utf8(body).ok())
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            StatusCode::Ok => "OK",
-            StatusCode::NotFound => "Not Found",
-            StatusCode::BadRequest => "Bad Request",
-            StatusCode::InternalServerError => "Internal Server Error",
+            Body::Full(body) => f.debug_tuple("Full").field(&String::from_utf8_lossy(body)).finish(),
+            Body::Stream(_) => f.debug_tuple("Stream").finish_non_exhaustive(),
         }
     }
 }
 
+impl PartialEq<[u8]> for Body {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_bytes() == Some(other)
+    }
+}
+
+impl PartialEq<&[u8]> for Body {
+    fn eq(&self, other: &&[u8]) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<str> for Body {
+    fn eq(&self, other: &str) -> bool {
+        self == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Body {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<String> for Body {
+    fn eq(&self, other: &String) -> bool {
+        self == other.as_str()
+    }
+}
+
+/// Iterator over the chunks of a reader, used by [`HttpResponse::from_reader`].
+struct ReaderStream<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> Iterator for ReaderStream<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut chunk = vec![0; STREAM_CHUNK_SIZE];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(n) => {
+                    chunk.truncate(n);
+                    return Some(Ok(chunk));
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Size of the chunks read by [`HttpResponse::from_reader`].
+const STREAM_CHUNK_SIZE: usize = 8192;
+
 /// Represents an HTTP response that can be sent back to the client.
 /// 
 /// An `HttpResponse` contains the status code, headers, and body of the response.
-/// It provides methods to create responses and convert them to the HTTP wire format.
+/// It provides methods to create responses and write them in the HTTP wire format.
 /// 
 /// # Examples
 /// 
@@ -205,9 +774,10 @@ impl StatusCode {
 /// use your_crate::{HttpResponse, StatusCode};
 /// 
 /// let response = HttpResponse::new(StatusCode::Ok, "Hello, World!".to_string());
-/// println!("{}", response.to_string());
+/// let mut bytes = Vec::new();
+/// response.write_to(&mut bytes).unwrap();
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Debug, Clone)]
 pub struct HttpResponse {
     /// The HTTP version (e.g., "HTTP/1.1")
     pub version: String,
@@ -215,10 +785,15 @@ pub struct HttpResponse {
     pub status: StatusCode,
     /// The status message (e.g., "OK", "Not Found")
     pub status_message: String,
-    /// HTTP headers as key-value pairs
-    pub headers: HashMap<String, String>,
+    /// HTTP headers, a name can have several values
+    pub headers: HeaderMap,
     /// The response body
-    pub body: String,
+    pub body: Body,
+    /// The error the response was created from, if it was created from an [`HttpError`].
+    /// 
+    /// An `App` renders it with its error handler, with the current status of the response.
+    /// Middlewares can read it e.g. to log errors.
+    pub error: Option<Box<HttpError>>,
 }
 
 impl HttpResponse {
@@ -230,7 +805,7 @@ impl HttpResponse {
     /// # Arguments
     /// 
     /// * `status` - The HTTP status code for the response
-    /// * `body` - The response body as a string
+    /// * `body` - The response body, e.g. a `String` or a `Vec<u8>`
     /// 
     /// # Examples
     /// 
@@ -241,34 +816,169 @@ impl HttpResponse {
     /// assert_eq!(response.status, StatusCode::Ok);
     /// assert_eq!(response.body, "Hello");
     /// ```
-    pub fn new(status: StatusCode, body: String) -> Self {
-        let mut headers = HashMap::new();
-        headers.insert("Content-Length".to_string(), body.len().to_string());
+    pub fn new(status: StatusCode, body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Length", body.len().to_string());
         
         Self {
             version: "HTTP/1.1".to_string(),
             status,
             status_message: status.reason_phrase().to_string(),
             headers,
-            body,
+            body: Body::Full(body),
+            error: None,
         }
     }
 
-    /// Converts the response to its HTTP string representation for sending over the network.
+    /// Creates a new HTTP response with a body produced incrementally by an iterator.
     /// 
-    /// The format follows the HTTP/1.1 specification with headers separated from the body by `\r\n\r\n`.
+    /// Every item of the iterator is sent as a separate chunk with `Transfer-Encoding: chunked`,
+    /// as soon as it is produced.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `status` - The HTTP status code for the response
+    /// * `chunks` - An iterator over the parts of the body
     /// 
     /// # Examples
     /// 
     /// ```
-    /// use your_crate::{HttpResponse, StatusCode};
+    /// use http::{HttpResponse, StatusCode};
     /// 
-    /// let response = HttpResponse::new(StatusCode::Ok, "Hello".to_string());
-    /// let http_string = response.to_string();
-    /// assert!(http_string.starts_with("HTTP/1.1 200 OK"));
+    /// let response = HttpResponse::stream(StatusCode::Ok, (1..=3).map(|i| format!("line {i}\n")));
+    /// assert!(response.body.is_stream());
     /// ```
-    pub fn to_string(&self) -> String {
-        let mut response = format!(
+    pub fn stream<I, T>(status: StatusCode, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+        T: Into<Vec<u8>>,
+    {
+        Self::from_body(status, Body::Stream(BodyStream::new(chunks.into_iter().map(|chunk| Ok(chunk.into())))))
+    }
+
+    /// Creates a new HTTP response with a body read incrementally from a reader.
+    /// 
+    /// The reader is consumed chunk by chunk while the response is being sent,
+    /// so large files do not have to be loaded into memory.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `status` - The HTTP status code for the response
+    /// * `reader` - A source of the body, e.g. a `File`
+    pub fn from_reader<R>(status: StatusCode, reader: R) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        Self::from_body(status, Body::Stream(BodyStream::new(ReaderStream { reader, done: false })))
+    }
+
+    /// Adds a `Set-Cookie` header, keeping the cookies set before.
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        self.headers.append("Set-Cookie", cookie.to_string());
+    }
+
+    fn from_body(status: StatusCode, body: Body) -> Self {
+        Self {
+            version: "HTTP/1.1".to_string(),
+            status,
+            status_message: status.reason_phrase().to_string(),
+            headers: HeaderMap::new(),
+            body,
+            error: None,
+        }
+    }
+
+    /// Writes the response in the HTTP wire format.
+    /// 
+    /// In-memory bodies are written with an up-to-date `Content-Length` header,
+    /// streamed bodies are written with `Transfer-Encoding: chunked`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `writer` - the destination, usually a client socket
+    pub fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        self.write(writer, true, false)
+    }
+
+    /// Writes the response, optionally without chunked encoding or without the body.
+    /// 
+    /// HTTP/1.0 clients do not understand chunked encoding, so streamed bodies are
+    /// written as-is for them and the end of the body is marked by closing the connection.
+    /// Responses to `HEAD` requests are written with the headers the body would have,
+    /// but without the body itself.
+    pub(crate) fn write<W: Write>(self, writer: &mut W, chunked: bool, head_only: bool) -> io::Result<()> {
+        if !self.status.is_valid() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid status code {}", self.status.as_u16()),
+            ));
+        }
+
+        let mut head = self.head(chunked).into_bytes();
+
+        if head_only || !self.has_body() {
+            writer.write_all(&head)?;
+            return writer.flush();
+        }
+
+        match self.body {
+            Body::Full(body) => {
+                head.extend_from_slice(&body);
+                writer.write_all(&head)?;
+            }
+            Body::Stream(chunks) => {
+                writer.write_all(&head)?;
+                for chunk in chunks {
+                    let chunk = chunk?;
+                    // An empty chunk would mark the end of the body
+                    if chunk.is_empty() {
+                        continue;
+                    }
+
+                    if chunked {
+                        let mut frame = format!("{:X}\r\n", chunk.len()).into_bytes();
+                        frame.extend_from_slice(&chunk);
+                        frame.extend_from_slice(b"\r\n");
+                        writer.write_all(&frame)?;
+                    } else {
+                        writer.write_all(&chunk)?;
+                    }
+                    writer.flush()?;
+                }
+                if chunked {
+                    writer.write_all(b"0\r\n\r\n")?;
+                }
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+impl HttpResponse {
+    /// Copies the headers of a replaced response, except the ones describing its body
+    /// and the ones this response sets itself.
+    pub(crate) fn keep_headers(&mut self, mut headers: HeaderMap) {
+        headers.retain(|name, _| {
+            !["Content-Length", "Content-Type", "Transfer-Encoding"].iter().any(|h| h.eq_ignore_ascii_case(name))
+                && !self.headers.contains_key(name)
+        });
+        for (name, value) in headers.iter() {
+            self.headers.append(name, value);
+        }
+    }
+
+    /// Returns `false` for the statuses which never have a body, 1xx, 204 and 304 (RFC 9110, section 6.4.1).
+    fn has_body(&self) -> bool {
+        !self.status.is_informational() && !matches!(self.status, StatusCode::NoContent | StatusCode::NotModified)
+    }
+
+    /// Returns the status line and the headers, with the headers framing the body
+    /// replaced by up-to-date ones.
+    fn head(&self, chunked: bool) -> String {
+        let mut head = format!(
             "{} {} {}\r\n",
             self.version,
             self.status.as_u16(),
@@ -277,13 +987,34 @@ impl HttpResponse {
 
         // Add headers
         for (key, value) in &self.headers {
-            response.push_str(&format!("{}: {}\r\n", key, value));
+            if !key.eq_ignore_ascii_case("Content-Length") && !key.eq_ignore_ascii_case("Transfer-Encoding") {
+                head.push_str(&format!("{}: {}\r\n", key, value));
+            }
         }
 
-        response.push_str("\r\n"); // End of headers
-        response.push_str(&self.body);
+        match &self.body {
+            _ if !self.has_body() => {}
+            Body::Full(body) => head.push_str(&format!("Content-Length: {}\r\n", body.len())),
+            Body::Stream(_) if chunked => head.push_str("Transfer-Encoding: chunked\r\n"),
+            Body::Stream(_) => {}
+        }
 
-        response
+        head.push_str("\r\n"); // End of headers
+        head
+    }
+}
+
+/// Formats the response in the HTTP wire format, e.g. with `response.to_string()`.
+/// 
+/// An in-memory body is converted to text lossily. A streamed body cannot be read
+/// without sending it, so only the head is formatted, use [`HttpResponse::write_to`] instead.
+impl std::fmt::Display for HttpResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.head(true))?;
+        match &self.body {
+            Body::Full(body) if self.has_body() => f.write_str(&String::from_utf8_lossy(body)),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -346,23 +1077,66 @@ impl Respondable for HttpResponse {
     }
 }
 
-/// Implements `Respondable` for `Result<T, String>`.
+/// Implements `Respondable` for `Result<T, E>` with any error converting into an [`HttpError`].
 /// 
 /// On success (`Ok`), converts the inner value to a response.
-/// On error (`Err`), creates a 500 Internal Server Error response with the error message as the body.
-impl<T> Respondable for Result<T, String>
+/// On error (`Err`), converts the error to a response, e.g. a `String` becomes
+/// a 500 Internal Server Error response with the error message as the body.
+impl<T, E> Respondable for Result<T, E>
 where
     T: Respondable,
+    E: Into<HttpError>,
 {
     fn into_response(self) -> HttpResponse {
         match self {
             Ok(value) => value.into_response(),
-            Err(error_msg) => HttpResponse::new(StatusCode::InternalServerError, error_msg),
+            Err(e) => e.into().into_response(),
         }
     }
 }
 
 
+/// Implements `Respondable` for `Vec<u8>`.
+/// 
+/// Creates a response with status 200 OK, the bytes as the body and
+/// `Content-Type: application/octet-stream`.
+impl Respondable for Vec<u8> {
+    fn into_response(self) -> HttpResponse {
+        let mut response = HttpResponse::new(StatusCode::Ok, self);
+        response.headers.insert("Content-Type", "application/octet-stream");
+        response
+    }
+}
+
+/// Implements `Respondable` for `&[u8]`.
+/// 
+/// Creates a response with status 200 OK, the bytes as the body and
+/// `Content-Type: application/octet-stream`.
+impl Respondable for &[u8] {
+    fn into_response(self) -> HttpResponse {
+        self.to_vec().into_response()
+    }
+}
+
+/// Implements `Respondable` for a status code and a value.
+/// 
+/// Converts the value to a response and replaces its status, e.g. `(StatusCode::Created, Json(user))`.
+impl<T> Respondable for (StatusCode, T)
+where
+    T: Respondable,
+{
+    fn into_response(self) -> HttpResponse {
+        let (status, value) = self;
+        let mut response = value.into_response();
+        response.status = status;
+        response.status_message = status.reason_phrase().to_string();
+        if let Some(error) = &mut response.error {
+            error.status = status;
+        }
+        response
+    }
+}
+
 /// For convenience, `Unit` type also implements Respondable.
 /// This allows no return in a handler.
 impl Respondable for () {
@@ -0,0 +1,365 @@
+use std::{collections::HashMap, io, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, MutexGuard}, time::{Duration, Instant}};
+
+use crate::app::Next;
+use crate::cookie::{Cookie, SameSite};
+use crate::crypto;
+use crate::jsonable::{JsonValue, Jsonable, Parser};
+use crate::error::HttpError;
+use crate::server::{HttpRequest, HttpResponse, Respondable};
+
+/// The data of a session.
+pub type SessionData = HashMap<String, JsonValue>;
+
+/// A storage backend of sessions.
+///
+/// Stores are shared between the worker threads of the server, so they must be `Send + Sync`.
+pub trait SessionStore: Send + Sync {
+    /// Returns the data of a session, `None` if it does not exist or has expired.
+    fn load(&self, id: &str) -> io::Result<Option<SessionData>>;
+
+    /// Stores the data of a session, which expires if it is not saved again within `ttl`.
+    fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> io::Result<()>;
+
+    /// Deletes a session.
+    fn remove(&self, id: &str) -> io::Result<()>;
+}
+
+/// How many saves a [`MemoryStore`] takes between two sweeps of expired sessions.
+const SWEEP_INTERVAL: usize = 1024;
+
+/// A session store keeping sessions in memory.
+///
+/// Sessions are lost when the process exits, and are not shared between processes.
+/// An expired session is dropped when it is loaded, the ones which are never loaded again
+/// are dropped by a sweep every 1024 saves.
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, (SessionData, Instant)>>,
+    saves: AtomicUsize,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of sessions which have not expired.
+    pub fn len(&self) -> usize {
+        let now = Instant::now();
+        self.lock().values().filter(|(_, expires)| *expires > now).count()
+    }
+
+    /// Returns `true` if there are no sessions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, (SessionData, Instant)>> {
+        // The map stays consistent even if a thread panicked while holding the lock
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> io::Result<Option<SessionData>> {
+        let mut sessions = self.lock();
+        match sessions.get(id) {
+            Some((data, expires)) if *expires > Instant::now() => Ok(Some(data.clone())),
+            Some(_) => {
+                sessions.remove(id);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> io::Result<()> {
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        if self.saves.fetch_add(1, Ordering::Relaxed) % SWEEP_INTERVAL == SWEEP_INTERVAL - 1 {
+            sessions.retain(|_, (_, expires)| *expires > now);
+        }
+        sessions.insert(id.to_string(), (data.clone(), now + ttl));
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> io::Result<()> {
+        self.lock().remove(id);
+        Ok(())
+    }
+}
+
+/// Settings of the session middleware.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// The name of the session cookie.
+    pub cookie_name: String,
+    /// The key used to sign session ids.
+    pub secret: Vec<u8>,
+    /// How long a session lives without requests.
+    pub max_age: Duration,
+    /// The `Path` of the session cookie.
+    pub path: String,
+    /// The `Domain` of the session cookie.
+    pub domain: Option<String>,
+    /// Whether the session cookie is only sent over HTTPS.
+    pub secure: bool,
+    /// The `SameSite` attribute of the session cookie.
+    pub same_site: SameSite,
+}
+
+impl SessionConfig {
+    /// Creates the default configuration with the given signing key:
+    ///
+    /// * a `session` cookie for `/`, which is `HttpOnly` and `SameSite=Lax`,
+    /// * sessions expire after a day without requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the secret is shorter than 32 bytes.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        let secret = secret.into();
+        assert!(secret.len() >= 32, "The session secret must be at least 32 bytes long");
+
+        Self {
+            cookie_name: "session".to_string(),
+            secret,
+            max_age: Duration::from_secs(24 * 60 * 60),
+            path: "/".to_string(),
+            domain: None,
+            secure: false,
+            same_site: SameSite::Lax,
+        }
+    }
+
+    fn sign(&self, id: &str) -> String {
+        format!("{}.{}", id, crypto::base64url(&crypto::hmac_sha256(&self.secret, id.as_bytes())))
+    }
+
+    /// Returns the session id of a cookie value if its signature is valid.
+    fn verify<'a>(&self, value: &'a str) -> Option<&'a str> {
+        let (id, _) = value.split_once('.')?;
+        crypto::constant_time_eq(self.sign(id).as_bytes(), value.as_bytes()).then_some(id)
+    }
+
+    fn cookie(&self, value: String) -> Cookie {
+        let cookie = Cookie::new(&self.cookie_name, value)
+            .path(&self.path)
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site);
+        match &self.domain {
+            Some(domain) => cookie.domain(domain),
+            None => cookie,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    /// `None` until the session is saved for the first time.
+    id: Option<String>,
+    data: SessionData,
+    regenerate: bool,
+    destroyed: bool,
+}
+
+/// The session of the current request.
+///
+/// Handlers get it with [`HttpRequest::session`]. Changes are saved after the handler returns.
+/// A new session is only stored, and its cookie only sent, once it holds data.
+///
+/// # Examples
+///
+/// ```
+/// use http::{App, HttpRequest, HttpResponse, JsonValue, MemoryStore, Respondable, SessionConfig};
+///
+/// let mut app = App::new();
+/// app.wrap(http::sessions(SessionConfig::new([7u8; 32]), MemoryStore::new()));
+///
+/// app.post("/login".to_string(), |req: HttpRequest| {
+///     let session = req.session().unwrap();
+///     // A new id prevents session fixation
+///     session.regenerate();
+///     session.insert_value("user", JsonValue::String("ann".to_string()));
+///     "Welcome".into_response()
+/// });
+///
+/// app.get("/me".to_string(), |req: HttpRequest| {
+///     match req.session().and_then(|s| s.get_value("user")) {
+///         Some(JsonValue::String(user)) => user.into_response(),
+///         _ => "Not logged in".into_response(),
+///     }
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl Session {
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the id of the session, `None` if it was not saved yet.
+    pub fn id(&self) -> Option<String> {
+        self.lock().id.clone()
+    }
+
+    /// Returns a value.
+    pub fn get_value(&self, key: &str) -> Option<JsonValue> {
+        self.lock().data.get(key).cloned()
+    }
+
+    /// Sets a value, replacing the previous one.
+    pub fn insert_value(&self, key: impl Into<String>, value: JsonValue) {
+        self.lock().data.insert(key.into(), value);
+    }
+
+    /// Returns a value converted into a `Jsonable` type.
+    ///
+    /// Returns `None` if the value is missing or cannot be converted.
+    pub fn get<T: Jsonable>(&self, key: &str) -> Option<T> {
+        let value = self.get_value(key)?;
+        T::from_json(&value.to_string()).ok()
+    }
+
+    /// Sets a value from a `Jsonable` type, replacing the previous one.
+    ///
+    /// Fails if the type does not produce valid JSON.
+    pub fn insert<T: Jsonable>(&self, key: impl Into<String>, value: &T) -> Result<(), String> {
+        let value = Parser::parse_json(&value.into_json())?;
+        self.insert_value(key, value);
+        Ok(())
+    }
+
+    /// Removes a value, returning it.
+    pub fn remove(&self, key: &str) -> Option<JsonValue> {
+        self.lock().data.remove(key)
+    }
+
+    /// Removes every value.
+    pub fn clear(&self) {
+        self.lock().data.clear();
+    }
+
+    /// Returns `true` if the session holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.lock().data.is_empty()
+    }
+
+    /// Moves the session to a new id, keeping its data.
+    ///
+    /// Call it whenever the privileges of a user change, e.g. on login,
+    /// so an id known to an attacker becomes useless.
+    pub fn regenerate(&self) {
+        self.lock().regenerate = true;
+    }
+
+    /// Deletes the session and its cookie, e.g. on logout.
+    pub fn destroy(&self) {
+        let mut state = self.lock();
+        state.destroyed = true;
+        state.data.clear();
+    }
+}
+
+/// Creates the session middleware, which is added with [`App::wrap`](crate::App::wrap).
+///
+/// The middleware reads the session id from an HMAC-signed cookie and loads the session
+/// from the store before the handler runs. Afterwards it saves the session, which also
+/// extends its lifetime, and sends the cookie.
+///
+/// # Arguments
+///
+/// * `config` - the cookie settings and the signing key
+/// * `store` - the storage backend, e.g. `MemoryStore`
+pub fn sessions<S>(config: SessionConfig, store: S) -> impl Fn(HttpRequest, Next<'_>) -> HttpResponse + Send + Sync + 'static
+where
+    S: SessionStore + 'static,
+{
+    let config = Arc::new(config);
+    let store = Arc::new(store);
+
+    move |mut req: HttpRequest, next: Next<'_>| {
+        let cookie_id = req
+            .cookies()
+            .get(&config.cookie_name)
+            .and_then(|value| config.verify(value))
+            .map(str::to_string);
+
+        let mut state = SessionState::default();
+        if let Some(id) = &cookie_id {
+            match store.load(id) {
+                Ok(Some(data)) => {
+                    state.id = Some(id.clone());
+                    state.data = data;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Error loading session: {:?}", e),
+            }
+        }
+
+        let session = Session { state: Arc::new(Mutex::new(state)) };
+        req.extensions.insert(session.clone());
+
+        let response = next.run(req);
+        finish_session(&session, cookie_id.is_some(), &config, store.as_ref(), response)
+    }
+}
+
+/// Saves or deletes the session after the handler returned.
+fn finish_session(session: &Session, had_cookie: bool, config: &SessionConfig, store: &dyn SessionStore, mut response: HttpResponse) -> HttpResponse {
+    let mut state = session.lock();
+
+    if state.destroyed {
+        if let Some(id) = state.id.take()
+            && let Err(e) = store.remove(&id)
+        {
+            eprintln!("Error removing session: {:?}", e);
+        }
+        if had_cookie {
+            response.set_cookie(config.cookie(String::new()).expires(std::time::UNIX_EPOCH).max_age(Duration::ZERO));
+        }
+        return response;
+    }
+
+    // Do not store empty sessions of anonymous clients
+    if state.id.is_none() && state.data.is_empty() {
+        return response;
+    }
+
+    if state.regenerate {
+        state.regenerate = false;
+        if let Some(old) = state.id.take()
+            && let Err(e) = store.remove(&old)
+        {
+            eprintln!("Error removing session: {:?}", e);
+        }
+    }
+
+    let id = match &state.id {
+        Some(id) => id.clone(),
+        None => match crypto::random_bytes::<32>() {
+            Ok(bytes) => state.id.insert(crypto::base64url(&bytes)).clone(),
+            Err(e) => return failed(response, format!("Error creating session id: {}", e)),
+        },
+    };
+    if let Err(e) = store.save(&id, &state.data, config.max_age) {
+        return failed(response, format!("Error saving session: {}", e));
+    }
+
+    response.set_cookie(config.cookie(config.sign(&id)).max_age(config.max_age));
+    response
+}
+
+/// Replaces the response with a `500 Internal Server Error`, because the changes of the session are lost.
+///
+/// The headers set by the handler and the middlewares are kept, but not its body.
+fn failed(response: HttpResponse, message: String) -> HttpResponse {
+    let mut failed = HttpError::internal(message).into_response();
+    failed.keep_headers(response.headers);
+    failed
+}
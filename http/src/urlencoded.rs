@@ -0,0 +1,144 @@
+/// Errors which can happen when reading a form body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormError {
+    /// The request has no `application/x-www-form-urlencoded` content type, the actual one is attached.
+    UnsupportedMediaType(Option<String>),
+    /// The body is not valid UTF-8.
+    InvalidBody,
+}
+
+impl std::fmt::Display for FormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType(Some(t)) => write!(f, "Unsupported media type: {}", t),
+            FormError::UnsupportedMediaType(None) => write!(f, "Missing Content-Type"),
+            FormError::InvalidBody => write!(f, "Form body is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+/// An ordered collection of decoded `key=value` pairs.
+///
+/// Used for query strings and `application/x-www-form-urlencoded` bodies.
+/// A key can appear several times, e.g. `?tag=a&tag=b`.
+///
+/// # Examples
+///
+/// ```
+/// use http::Params;
+///
+/// let params = Params::parse("tag=a&tag=b&q=hello+world%21");
+///
+/// assert_eq!(params.get("q"), Some("hello world!"));
+/// assert_eq!(params.get_all("tag"), vec!["a", "b"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
+impl Params {
+    /// Creates an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a url-encoded string such as `a=1&b=two+words`.
+    ///
+    /// `+` is decoded as a space and `%XX` sequences as bytes.
+    /// A key without `=` gets an empty value, empty pairs are skipped.
+    pub fn parse(input: &str) -> Self {
+        let pairs = input
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(key), decode_component(value))
+            })
+            .collect();
+
+        Self { pairs }
+    }
+
+    /// Returns the first value of a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns every value of a key, in order.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Returns `true` if the key is present.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Adds a value to a key, keeping its previous values.
+    pub fn append(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.pairs.push((key.into(), value.into()));
+    }
+
+    /// Returns an iterator over all pairs, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns `true` if there are no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// Decodes `%XX` sequences in a string.
+///
+/// Invalid sequences are kept as they are, and invalid UTF-8 is replaced with `U+FFFD`.
+/// Unlike query strings, `+` is not decoded, as it means a plus sign in a path.
+pub fn percent_decode(input: &str) -> String {
+    decode(input, false)
+}
+
+/// Decodes a key or a value of a url-encoded string.
+fn decode_component(input: &str) -> String {
+    decode(input, true)
+}
+
+fn decode(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(&[hi, lo]) if bytes[i] == b'%' => hex_value(hi).zip(hex_value(lo)),
+            _ => None,
+        };
+
+        if let Some((hi, lo)) = escaped {
+            decoded.push(hi * 16 + lo);
+            i += 3;
+            continue;
+        }
+
+        decoded.push(if bytes[i] == b'+' && plus_as_space { b' ' } else { bytes[i] });
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
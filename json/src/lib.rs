@@ -57,12 +57,26 @@ pub fn derive_jsonable(input: TokenStream) -> TokenStream {
         quote! {
             #field_name: {
                 let field_json = get_field_val(stringify!(#field_name))?;
-                #field_type::from_json_value(field_json)
+                <#field_type as FromJsonValue>::from_json_value(field_json)
                     .map_err(|e| format!("Failed to convert field '{}' from JSON: {}", stringify!(#field_name), e))?
             },
         }
     });
 
+    // Generate form decoding code for each field
+    let form_fields = named_fields.iter().map(|field| {
+        let field_name = &field.ident;
+        let field_type = &field.ty;
+
+        quote! {
+            #field_name: {
+                let values = params.get_all(stringify!(#field_name));
+                <#field_type as http::jsonable::FromFormValue>::from_form_values(&values)
+                    .map_err(|e| format!("Failed to convert field '{}' from form: {}", stringify!(#field_name), e))?
+            },
+        }
+    });
+
     let generated = quote! {
         use http::jsonable::{Parser, JsonValue, FromJsonValue};
 
@@ -89,7 +103,10 @@ pub fn derive_jsonable(input: TokenStream) -> TokenStream {
             fn from_json(json_string: &str) -> Result<Self, Box<dyn std::error::Error>> {
                 let parsed = Parser::parse_json(json_string)
                     .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+                Self::from_json_value(&parsed)
+            }
 
+            fn from_json_value(parsed: &JsonValue) -> Result<Self, Box<dyn std::error::Error>> {
                 let members = if let JsonValue::Object(members) = parsed {
                     members
                 } else {
@@ -107,6 +124,12 @@ pub fn derive_jsonable(input: TokenStream) -> TokenStream {
                     #(#deserialize_fields)*
                 })
             }
+
+            fn from_form(params: &http::Params) -> Result<Self, Box<dyn std::error::Error>> {
+                Ok(#name {
+                    #(#form_fields)*
+                })
+            }
         }
     };
 